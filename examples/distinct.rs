@@ -90,8 +90,8 @@ fn _distinct<C: Communicator>(allocator: C) {
     graph.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new());
 
     // move some data into the dataflow graph.
-    input1.send_messages(&((), 0), vec![1u64]);
-    input2.send_messages(&((), 0), vec![2u64]);
+    input1.send_messages(&((), 0), vec![1u64]).ok().expect("input congested");
+    input2.send_messages(&((), 0), vec![2u64]).ok().expect("input congested");
 
     // see what everyone thinks about that ...
     graph.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new());
@@ -1,32 +1,126 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
-use std::any::Any;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::any::{Any, TypeId};
+use std::hash::{hash, SipHasher};
+use std::fmt::{self, Display, Formatter};
+use std::io::{Result, Error, ErrorKind};
 use std::sync::mpsc::{Sender, Receiver, channel};
 
 use core::marker::PhantomData;
 
 use columnar::{Columnar, ColumnarStack};
-use communication::{Observer, Pushable, Pullable};
-use networking::networking::MessageHeader;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use communication::{Observer, Pushable, Pullable, PushHandle, PullHandle, Signal};
+use networking::networking::{MessageHeader, Failure, NetworkThreads, Unclaimed, Layouts, compare_layout, record_mismatch, NetworkStatistics, FLAG_GRANT, FLAG_PRIORITY, FLAG_ANNOUNCE, FLAG_LAYOUT, FLAG_COMPRESSED, DEFAULT_CREDITS, GRANT_BATCH, MAX_LENGTH};
+use networking::compress::decompress;
 use std::default::Default;
 
 // The Communicator trait presents the interface a worker has to the outside world.
 // The worker can see its index, the total number of peers, and acquire channels to and from the other workers.
 // There is an assumption that each worker performs the same channel allocation logic; things go wrong otherwise.
+// Each allocation is tagged with the name of the allocating operator and the type of its messages, so that
+// communicators can report workers whose allocations diverge, rather than misroute their messages.
 pub trait Communicator : 'static {
     fn index(&self) -> u64;     // number out of peers
     fn peers(&self) -> u64;     // number of peers
-    fn new_channel<T:Send+Columnar+Any>(&mut self) -> (Vec<Box<Pushable<T>>>, Box<Pullable<T>>);
+    fn new_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>);
+
+    // as new_channel, but for latency-sensitive control traffic (e.g. progress) that should not wait behind data.
+    fn new_priority_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        self.new_channel(name)
+    }
+
+    // number of sent messages held back for lack of credit at their destinations, over all channels.
+    // operators deciding whether to produce more should ask their outputs (see Pushable::congested) instead.
+    fn backlog(&self) -> u64 { 0 }
+
+    // a failure that leaves this worker unable to complete its dataflow, if any: a failed peer process, or workers
+    // that built different dataflows. workers should check this as they step the computation, and shut down if it is set.
+    fn failure(&self) -> Option<Failure> { None }
+
+    // if progress updates should be merged within groups of this many consecutive workers (e.g. the workers of one
+    // process) before being exchanged between groups, the size of the groups.
+    fn progress_groups(&self) -> Option<u64> { None }
+
+    // wakes this worker when parked, if other threads can deliver it messages. a worker may park on its signal when
+    // a step finds nothing to do, rather than spinning; without a signal, it should keep stepping.
+    fn signal(&self) -> Option<Signal> { None }
+
+    // called as the worker steps its dataflow, which is then built; channels allocated afterwards go unchecked.
+    // communicators may use it to check that the workers of other processes allocated the same channels.
+    fn allocation_complete(&mut self) { }
 }
 
-// TODO : Would be nice if Communicator had associated types for its Pushable and Pullable types,
-// TODO : but they would have to be generic over T, with the current set-up. Might require HKT?
+// Communicator can't have associated types for its Pushable and Pullable types, as they would have to be generic
+// over T (HKT). Instead channels are handed out as PushHandles and PullHandles, which are enums over the common
+// channel types, and only box the others.
 
 impl<C: Communicator> Communicator for Rc<RefCell<C>> {
     fn index(&self) -> u64 { self.borrow().index() }
     fn peers(&self) -> u64 { self.borrow().peers() }
-    fn new_channel<T:Send+Columnar+Any>(&mut self) -> (Vec<Box<Pushable<T>>>, Box<Pullable<T>>) { self.borrow_mut().new_channel() }
+    fn new_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) { self.borrow_mut().new_channel(name) }
+    fn new_priority_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) { self.borrow_mut().new_priority_channel(name) }
+    fn backlog(&self) -> u64 { self.borrow().backlog() }
+    fn failure(&self) -> Option<Failure> { self.borrow().failure() }
+    fn progress_groups(&self) -> Option<u64> { self.borrow().progress_groups() }
+    fn signal(&self) -> Option<Signal> { self.borrow().signal() }
+    fn allocation_complete(&mut self) { self.borrow_mut().allocation_complete() }
+}
+
+// What a worker allocated a channel for: the allocating operator, and a fingerprint of the type of its messages.
+// Fingerprints are only comparable between processes running the same binary, which the handshake checks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelTag {
+    pub name:           String,     // name of the allocating operator
+    pub fingerprint:    u64,        // hash of the TypeId of the channel's messages
+}
+
+impl ChannelTag {
+    pub fn new<T: Any>(name: &str) -> ChannelTag {
+        ChannelTag { name: name.to_string(), fingerprint: hash::<_, SipHasher>(&TypeId::of::<T>()) }
+    }
+
+    // the fingerprint as a little-endian u64, followed by the bytes of the name.
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.write_u64::<LittleEndian>(self.fingerprint).unwrap();
+        bytes.push_all(self.name.as_bytes());
+    }
+
+    pub fn decode(mut bytes: &[u8]) -> Result<ChannelTag> {
+        let fingerprint = try!(bytes.read_u64::<LittleEndian>());
+        let name = try!(String::from_utf8(bytes.to_vec()).map_err(|_| Error::new(ErrorKind::Other, "channel name is not utf8")));
+        Ok(ChannelTag { name: name, fingerprint: fingerprint })
+    }
+
+    // a sequence of tags, each encoded as its length as a little-endian u64, followed by the tag.
+    pub fn encode_all(tags: &[ChannelTag], bytes: &mut Vec<u8>) {
+        for tag in tags.iter() {
+            let mut encoded = Vec::new();
+            tag.encode(&mut encoded);
+            bytes.write_u64::<LittleEndian>(encoded.len() as u64).unwrap();
+            bytes.push_all(&encoded[..]);
+        }
+    }
+
+    pub fn decode_all(mut bytes: &[u8]) -> Result<Vec<ChannelTag>> {
+        let mut tags = Vec::new();
+        while bytes.len() > 0 {
+            let length = try!(bytes.read_u64::<LittleEndian>()) as usize;
+            if length > bytes.len() { return Err(Error::new(ErrorKind::Other, "truncated channel tags")); }
+            tags.push(try!(ChannelTag::decode(&bytes[..length])));
+            bytes = &bytes[length..];
+        }
+        Ok(tags)
+    }
+}
+
+impl Display for ChannelTag {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "operator {:?} (message type {:016x})", self.name, self.fingerprint)
+    }
 }
 
 // The simplest communicator remains worker-local and just queues sent messages.
@@ -34,9 +128,9 @@ pub struct ThreadCommunicator;
 impl Communicator for ThreadCommunicator {
     fn index(&self) -> u64 { 0 }
     fn peers(&self) -> u64 { 1 }
-    fn new_channel<T:'static>(&mut self) -> (Vec<Box<Pushable<T>>>, Box<Pullable<T>>) {
-        let shared = Rc::new(RefCell::new(Vec::<T>::new()));
-        return (vec![Box::new(shared.clone()) as Box<Pushable<T>>], Box::new(shared.clone()) as Box<Pullable<T>>)
+    fn new_channel<T:'static>(&mut self, _name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        let shared = Rc::new(RefCell::new(VecDeque::<T>::new()));
+        return (vec![PushHandle::Thread(shared.clone())], PullHandle::Thread(shared.clone()))
     }
 }
 
@@ -47,58 +141,138 @@ pub struct ProcessCommunicator {
     index:      u64,                            // number out of peers
     peers:      u64,                            // number of peer allocators (for typed channel allocation).
     allocated:  u64,                            // indicates how many have been allocated (locally).
-    channels:   Arc<Mutex<Vec<Box<Any+Send>>>>, // Box<Any+Send> -> Box<Vec<Option<(Vec<Sender<T>>, Receiver<T>)>>>
+    channels:   Arc<Mutex<Vec<(u64, ChannelTag, Box<Any+Send>)>>>,  // (first allocator, its tag, channels)
+                                                // Box<Any+Send> -> Box<Vec<Arc<Mutex<VecDeque<T>>>>>
+    signals:    Vec<Signal>,                    // signal of each worker, notified by pushes to it
 }
 
 impl ProcessCommunicator {
     pub fn inner<'a>(&'a mut self) -> &'a mut ThreadCommunicator { &mut self.inner }
     pub fn new_vector(count: u64) -> Vec<ProcessCommunicator> {
         let channels = Arc::new(Mutex::new(Vec::new()));
+        let signals: Vec<Signal> = (0 .. count).map(|_| Signal::new()).collect();
         return (0 .. count).map(|index| ProcessCommunicator {
             inner:      ThreadCommunicator,
             index:      index,
             peers:      count,
             allocated:  0,
             channels:   channels.clone(),
+            signals:    signals.clone(),
         }).collect();
     }
 }
 
-impl Communicator for ProcessCommunicator {
-    fn index(&self) -> u64 { self.index }
-    fn peers(&self) -> u64 { self.peers }
-    fn new_channel<T:Send+Any>(&mut self) -> (Vec<Box<Pushable<T>>>, Box<Pullable<T>>) {
+impl ProcessCommunicator {
+    // allocates a channel of T, which workers allocating the same channel must agree on the tag of.
+    fn new_tagged_channel<T:Send+Any>(&mut self, tag: ChannelTag) -> (Vec<PushHandle<T>>, PullHandle<T>) {
         let mut channels = self.channels.lock().ok().expect("mutex error?");
         if self.allocated == channels.len() as u64 {  // we need a new channel ...
-            let mut senders = Vec::new();
-            let mut receivers = Vec::new();
-            for _ in (0..self.peers) {
-                let (s, r): (Sender<T>, Receiver<T>) = channel();
-                senders.push(s);
-                receivers.push(r);
-            }
-
-            let mut to_box = Vec::new();
-            for recv in receivers.drain() {
-                to_box.push(Some((senders.clone(), recv)));
-            }
+            // a queue for each worker, which any worker may push to and only its owner pulls from
+            let queues: Vec<Arc<Mutex<VecDeque<T>>>> = (0..self.peers).map(|_| Arc::new(Mutex::new(VecDeque::new()))).collect();
+            channels.push((self.index, tag.clone(), Box::new(queues)));
+        }
 
-            channels.push(Box::new(to_box));
+        let entry = &mut channels[self.allocated as usize];
+        if entry.1 != tag {
+            panic!("worker {}: channel {} allocated for {}, but by worker {} for {}; workers must build the same dataflow",
+                   self.index, self.allocated, tag, entry.0, entry.1);
         }
 
-        match channels[self.allocated as usize].downcast_mut::<(Vec<Option<(Vec<Sender<T>>, Receiver<T>)>>)>() {
-            Some(ref mut vector) => {
+        match entry.2.downcast_ref::<Vec<Arc<Mutex<VecDeque<T>>>>>() {
+            Some(queues) => {
                 self.allocated += 1;
-                let (mut send, recv) = vector[self.index as usize].take().unwrap();
-                let mut temp = Vec::new();
-                for s in send.drain() { temp.push(Box::new(s) as Box<Pushable<T>>); }
-                return (temp, Box::new(recv) as Box<Pullable<T>>)
+                let pushers = queues.iter().zip(self.signals.iter()).map(|(queue, signal)| PushHandle::Process(queue.clone(), signal.clone())).collect();
+                return (pushers, PullHandle::Process(queues[self.index as usize].clone()))
             }
             _ => { panic!("unable to cast channel correctly"); }
         }
     }
 }
 
+impl Communicator for ProcessCommunicator {
+    fn index(&self) -> u64 { self.index }
+    fn peers(&self) -> u64 { self.peers }
+    fn signal(&self) -> Option<Signal> { Some(self.signals[self.index as usize].clone()) }
+    fn new_channel<T:Send+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        self.new_tagged_channel(ChannelTag::new::<T>(name))
+    }
+}
+
+
+// A ProcessCommunicator whose channels carry serialized bytes, so that every message goes through the same Columnar
+// encode and decode as messages between processes do. For testing the Columnar implementations of data types in a
+// single process; bugs surface as panics at the receiving worker.
+pub struct LoopbackCommunicator {
+    inner:      ProcessCommunicator,    // allocates the Vec<u8> channels messages are sent over
+}
+
+impl LoopbackCommunicator {
+    pub fn new_vector(count: u64) -> Vec<LoopbackCommunicator> {
+        ProcessCommunicator::new_vector(count).into_iter().map(|inner| LoopbackCommunicator { inner: inner }).collect()
+    }
+}
+
+impl Communicator for LoopbackCommunicator {
+    fn index(&self) -> u64 { self.inner.index() }
+    fn peers(&self) -> u64 { self.inner.peers() }
+    fn signal(&self) -> Option<Signal> { self.inner.signal() }
+    fn new_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        let (senders, receiver) = self.inner.new_tagged_channel::<Vec<u8>>(ChannelTag::new::<T>(name));
+        let pushers = senders.into_iter().map(|sender| PushHandle::Boxed(Box::new(LoopbackPushable {
+            sender:     sender,
+            stack:      Default::default(),
+        }) as Box<Pushable<T>>)).collect();
+        let pullable = PullHandle::Boxed(Box::new(LoopbackPullable {
+            index:      self.inner.index(),
+            receiver:   receiver,
+            stack:      Default::default(),
+        }) as Box<Pullable<T>>);
+        (pushers, pullable)
+    }
+}
+
+struct LoopbackPushable<T: Columnar> {
+    sender:     PushHandle<Vec<u8>>,
+    stack:      <T as Columnar>::Stack,
+}
+
+impl<T:Columnar+'static> Pushable<T> for LoopbackPushable<T> {
+    #[inline]
+    fn push(&mut self, data: T) {
+        let mut bytes = Vec::new();
+        self.stack.push(data);
+        self.stack.encode(&mut bytes).unwrap();
+        self.sender.push(bytes);
+    }
+}
+
+struct LoopbackPullable<T: Columnar> {
+    index:      u64,                    // index of this worker, for reporting
+    receiver:   PullHandle<Vec<u8>>,
+    stack:      <T as Columnar>::Stack,
+}
+
+impl<T:Columnar+'static> Pullable<T> for LoopbackPullable<T> {
+    #[inline]
+    fn pull(&mut self) -> Option<T> {
+        if let Some(bytes) = self.receiver.pull() {
+            // each message should decode from exactly the bytes it encoded to, and to exactly one record
+            let mut slice = &bytes[..];
+            if let Err(error) = self.stack.decode(&mut slice) {
+                panic!("worker {}: failed to decode {} byte message: {}", self.index, bytes.len(), error);
+            }
+            if slice.len() > 0 {
+                panic!("worker {}: {} of {} bytes left over after decoding message", self.index, slice.len(), bytes.len());
+            }
+            let result = self.stack.pop();
+            if result.is_none() || self.stack.pop().is_some() {
+                panic!("worker {}: {} byte message did not decode to exactly one record", self.index, bytes.len());
+            }
+            result
+        }
+        else { None }
+    }
+}
 
 // A communicator intended for binary channels (networking, pipes, shared memory)
 pub struct BinaryCommunicator {
@@ -109,41 +283,76 @@ pub struct BinaryCommunicator {
     pub allocated:  u64,                    // indicates how many channels have been allocated (locally).
 
     // for loading up state in the networking threads.
-    pub writers:    Vec<Sender<((u64, u64, u64), Sender<Vec<u8>>)>>,                     // (index, back-to-worker)
-    pub readers:    Vec<Sender<((u64, u64, u64), Sender<Vec<u8>>, Receiver<Vec<u8>>)>>,  // (index, data-to-worker, back-from-worker)
-    pub senders:    Vec<Sender<(MessageHeader, Vec<u8>)>>                                // for sending bytes!
+    pub writers:    Vec<Sender<((u64, u64, u64, u64), Sender<Vec<u8>>, Arc<AtomicUsize>)>>,  // (index, back-to-worker, outstanding)
+    pub readers:    Vec<Sender<((u64, u64, u64), ChannelTag, Sender<(MessageHeader, Vec<u8>)>, Receiver<Vec<u8>>)>>,  // (index, tag, data-to-worker, back-from-worker)
+    pub senders:    Vec<Sender<(MessageHeader, Vec<u8>)>>,                               // for sending bytes!
+
+    pub backlog:    Arc<AtomicUsize>,       // messages held back by the networking threads for lack of credit
+    pub failure:    Arc<Mutex<Option<Failure>>>,        // the first failure observed by this process
+    pub unclaimed:  Unclaimed,              // messages that arrived before their channel was allocated here
+    pub layouts:    Layouts,                // the channels allocated by the first worker to report, per graph
+    pub tags:       Vec<ChannelTag>,        // the channels this worker allocated, in order
+    pub reported:   bool,                   // whether tags have been sent to the other processes
+    pub statistics: Vec<Arc<Mutex<NetworkStatistics>>>, // traffic counts kept by each connection's networking threads
+    pub aggregate:  bool,                   // merge progress updates within this process before sending them to others
+    pub prioritize: bool,                   // write priority channels' messages ahead of bulk data
+    pub network:    Arc<NetworkThreads>,    // shuts the networking threads down once the last worker is done with them
 }
 
 impl BinaryCommunicator {
     pub fn inner<'a>(&'a mut self) -> &'a mut ProcessCommunicator { &mut self.inner }
-}
 
-// A Communicator backed by Sender<Vec<u8>>/Receiver<Vec<u8>> pairs (e.g. networking, shared memory, files, pipes)
-impl Communicator for BinaryCommunicator {
-    fn index(&self) -> u64 { self.index }
-    fn peers(&self) -> u64 { self.peers }
-    fn new_channel<T:Send+Columnar+Any>(&mut self) -> (Vec<Box<Pushable<T>>>, Box<Pullable<T>>) {
-        let mut pushers: Vec<Box<Pushable<T>>> = Vec::new(); // built-up vector of Box<Pushable<T>> to return
+    // with aggregation, only one worker per process exchanges progress updates with other processes, sending the
+    // merged updates of its process and relaying those it receives to the other workers of its process.
+    pub fn aggregate_progress(mut self, aggregate: bool) -> BinaryCommunicator { self.aggregate = aggregate; self }
+
+    // without prioritization, priority channels are ordinary channels; for measuring what prioritization buys.
+    pub fn prioritize(mut self, prioritize: bool) -> BinaryCommunicator { self.prioritize = prioritize; self }
+
+    // messages and bytes sent and received so far by this process, for each (graph, channel, source, target).
+    // counts cover all workers of the process, not only this one; filter by source or target to separate them.
+    pub fn statistics(&self) -> NetworkStatistics {
+        let mut result = NetworkStatistics::default();
+        for statistics in self.statistics.iter() {
+            result.merge(&statistics.lock().ok().expect("mutex error?"));
+        }
+        result
+    }
+
+    // allocates a channel whose remote messages carry the supplied FLAG_* bits
+    fn new_flagged_channel<T:Send+Columnar+Any>(&mut self, name: &str, flags: u64) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        let mut pushers: Vec<PushHandle<T>> = Vec::new(); // built-up vector of PushHandle<T> to return
+        let tag = ChannelTag::new::<T>(name);
+        self.tags.push(tag.clone());
 
         // we'll need process-local channels as well (no self-loop binary connection in this design; perhaps should allow)
         let inner_peers = self.inner.peers();
-        let (inner_sends, inner_recv) = self.inner.new_channel();
+        let (inner_sends, inner_recv) = self.inner.new_tagged_channel(tag.clone());
 
         // prep a pushable for each endpoint, multiplied by inner_peers
         for (index, writer) in self.writers.iter().enumerate() {
             for _ in (0..inner_peers) {
                 let (s,r) = channel();  // generate a binary (Vec<u8>) channel pair of (back_to_worker, back_from_net)
                 let target_index = if index as u64 >= (self.index * inner_peers) { index as u64 + inner_peers } else { index as u64 };
+                let outstanding = Arc::new(AtomicUsize::new(0));
                 println!("init'ing send channel: ({} {} {})", self.index, self.graph, self.allocated);
-                writer.send(((self.index, self.graph, self.allocated), s)).unwrap();
+                writer.send(((self.index, self.graph, self.allocated, target_index), s, outstanding.clone())).ok();    // failed connections surface through failure()
                 let header = MessageHeader {
                     graph:      self.graph,
                     channel:    self.allocated,
                     source:     self.index,
                     target:     target_index,
                     length:     0,
+                    flags:      flags,
                 };
-                pushers.push(Box::new(BinaryPushable::new(header, self.senders[index].clone(), r)));
+
+                // announce the channel ahead of any messages, so that the receiving process can check it allocated the same
+                let mut bytes = Vec::new();
+                tag.encode(&mut bytes);
+                let announcement = MessageHeader { length: bytes.len() as u64, flags: FLAG_ANNOUNCE, .. header };
+                self.senders[index].send((announcement, bytes)).ok();
+
+                pushers.push(PushHandle::Boxed(Box::new(BinaryPushable::new(header, self.senders[index].clone(), r, outstanding)) as Box<Pushable<T>>));
             }
         }
 
@@ -152,22 +361,33 @@ impl Communicator for BinaryCommunicator {
             pushers.insert((self.index * inner_peers) as usize + index, writer);
         }
 
-        // prep a Box<Pullable<T>> using inner_recv and fresh registered pullables
+        // prep a PullHandle<T> using inner_recv and fresh registered pullables
         let (send,recv) = channel();    // binary channel from binary listener to BinaryPullable<T>
         let mut pullsends = Vec::new();
         for reader in self.readers.iter() {
             let (s,r) = channel();
             pullsends.push(s);
             println!("init'ing recv channel: ({} {} {})", self.index, self.graph, self.allocated);
-            reader.send(((self.index, self.graph, self.allocated), send.clone(), r)).unwrap();
+            reader.send(((self.index, self.graph, self.allocated), tag.clone(), send.clone(), r)).ok();
+        }
+
+        // messages that arrived before the registrations above were seen are ours to deliver, ahead of any the
+        // networking threads deliver once they see them (they look with the same lock held).
+        if let Some(messages) = self.unclaimed.lock().ok().expect("mutex error?").remove(&(self.index, self.graph, self.allocated)) {
+            for message in messages.into_iter() { send.send(message).ok(); }
         }
 
-        let pullable = Box::new(BinaryPullable {
+        let pullable = PullHandle::Boxed(Box::new(BinaryPullable {
             inner:      inner_recv,
+            index:      self.index,
+            workers:    inner_peers,
             senders:    pullsends,
+            grants:     self.senders.clone(),
+            granted:    Vec::new(),
             receiver:   recv,
             stack:      Default::default(),
-        });
+            decompressed: Vec::new(),
+        }) as Box<Pullable<T>>);
 
         self.allocated += 1;
 
@@ -175,21 +395,69 @@ impl Communicator for BinaryCommunicator {
     }
 }
 
+// A Communicator backed by Sender<Vec<u8>>/Receiver<Vec<u8>> pairs (e.g. networking, shared memory, files, pipes)
+impl Communicator for BinaryCommunicator {
+    fn index(&self) -> u64 { self.index }
+    fn peers(&self) -> u64 { self.peers }
+    fn backlog(&self) -> u64 { self.backlog.load(Ordering::SeqCst) as u64 }
+    fn failure(&self) -> Option<Failure> { self.failure.lock().ok().expect("mutex error?").clone() }
+    fn progress_groups(&self) -> Option<u64> { if self.aggregate { Some(self.inner.peers()) } else { None } }
+    fn signal(&self) -> Option<Signal> { self.inner.signal() }
+
+    // sends the channels we allocated to the first worker of each other process, and compares them with the first
+    // layout reported here. processes that built different dataflows would otherwise wait on each other's channels.
+    fn allocation_complete(&mut self) {
+        if self.reported { return; }
+        self.reported = true;
+
+        let mut bytes = Vec::new();
+        ChannelTag::encode_all(&self.tags[..], &mut bytes);
+        let workers = self.inner.peers();
+        let process = self.index / workers;
+        for (index, sender) in self.senders.iter().enumerate() {
+            let remote = if index as u64 >= process { index as u64 + 1 } else { index as u64 };
+            let header = MessageHeader {
+                graph:      self.graph,
+                channel:    0,
+                source:     self.index,
+                target:     remote * workers,
+                length:     bytes.len() as u64,
+                flags:      FLAG_LAYOUT,
+            };
+            sender.send((header, bytes.clone())).ok();    // failed connections surface through failure()
+        }
+
+        if let Err(mismatch) = compare_layout(&self.layouts, self.graph, self.index, self.tags.clone()) {
+            record_mismatch(&self.failure, mismatch);
+        }
+    }
+
+    fn new_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        self.new_flagged_channel(name, 0)
+    }
+    fn new_priority_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        let flags = if self.prioritize { FLAG_PRIORITY } else { 0 };
+        self.new_flagged_channel(name, flags)
+    }
+}
+
 struct BinaryPushable<T: Columnar> {
     header:     MessageHeader,
     sender:     Sender<(MessageHeader, Vec<u8>)>,   // targets for each remote destination
     receiver:   Receiver<Vec<u8>>,                  // source of empty binary vectors
+    outstanding:Arc<AtomicUsize>,                   // messages pushed but not yet consumed, as the BinarySender hears
     phantom:    PhantomData<T>,
     buffer:     Vec<u8>,
     stack:      <T as Columnar>::Stack,
 }
 
 impl<T: Columnar> BinaryPushable<T> {
-    pub fn new(header: MessageHeader, sender: Sender<(MessageHeader, Vec<u8>)>, receiver: Receiver<Vec<u8>>) -> BinaryPushable<T> {
+    pub fn new(header: MessageHeader, sender: Sender<(MessageHeader, Vec<u8>)>, receiver: Receiver<Vec<u8>>, outstanding: Arc<AtomicUsize>) -> BinaryPushable<T> {
         BinaryPushable {
             header:     header,
             sender:     sender,
             receiver:   receiver,
+            outstanding:outstanding,
             phantom:    PhantomData,
             buffer:     Vec::new(),
             stack:      Default::default(),
@@ -209,26 +477,165 @@ impl<T:Columnar+'static> Pushable<T> for BinaryPushable<T> {
         let mut header = self.header;
         header.length = bytes.len() as u64;
 
+        self.outstanding.fetch_add(1, Ordering::SeqCst);
         self.sender.send((header, bytes)).ok();
     }
+
+    // once the target holds all the credit it has granted, more messages would only wait in the BinarySender.
+    #[inline]
+    fn congested(&self) -> bool { self.outstanding.load(Ordering::SeqCst) as u64 >= DEFAULT_CREDITS }
 }
 
 struct BinaryPullable<T: Columnar> {
-    inner:      Box<Pullable<T>>,       // inner pullable (e.g. intra-process typed queue)
-    senders:    Vec<Sender<Vec<u8>>>,   // places to put used binary vectors
-    receiver:   Receiver<Vec<u8>>,      // source of serialized buffers
+    inner:      PullHandle<T>,          // inner pullable (e.g. intra-process typed queue)
+    index:      u64,                    // index of this worker
+    workers:    u64,                    // workers per process, to find the process of a source worker
+    senders:    Vec<Sender<Vec<u8>>>,   // places to put used binary vectors, indexed by remote process
+    grants:     Vec<Sender<(MessageHeader, Vec<u8>)>>,  // for returning credit, indexed by remote process
+    granted:    Vec<u64>,               // consumed messages not yet granted back, indexed by source worker
+    receiver:   Receiver<(MessageHeader, Vec<u8>)>,     // source of serialized buffers
     stack:      <T as Columnar>::Stack,
+    decompressed: Vec<u8>,              // scratch space for compressed messages
+}
+
+impl<T: Columnar> BinaryPullable<T> {
+    // index into per-remote-process vectors for a source worker (the local process has no entry)
+    fn remote(&self, worker: u64) -> usize {
+        let process = worker / self.workers;
+        let local = self.index / self.workers;
+        (if process > local { process - 1 } else { process }) as usize
+    }
+
+    // records consumption of a message, returning credit to its sender in batches
+    fn grant(&mut self, header: MessageHeader) {
+        let source = header.source as usize;
+        while self.granted.len() <= source { self.granted.push(0); }
+        self.granted[source] += 1;
+
+        if self.granted[source] >= GRANT_BATCH {
+            let mut bytes = Vec::new();
+            bytes.write_u64::<LittleEndian>(self.granted[source]).unwrap();
+            let grant = MessageHeader {
+                graph:      header.graph,
+                channel:    header.channel,
+                source:     self.index,
+                target:     header.source,
+                length:     bytes.len() as u64,
+                flags:      FLAG_GRANT,
+            };
+            let remote = self.remote(header.source);
+            self.grants[remote].send((grant, bytes)).ok();
+            self.granted[source] = 0;
+        }
+    }
+}
+
+impl<T: Columnar> BinaryPullable<T> {
+    // decodes a received message, returning its buffer to the networking threads and credit to its sender
+    fn decode(&mut self, header: MessageHeader, bytes: Vec<u8>) -> Option<T> {
+        if header.flags & FLAG_COMPRESSED != 0 {
+            if let Err(error) = decompress(&bytes[..], &mut self.decompressed, MAX_LENGTH) {
+                panic!("worker {}: message from worker {}: {}", self.index, header.source, error);
+            }
+            self.stack.decode(&mut &self.decompressed[..]).unwrap();
+        }
+        else { self.stack.decode(&mut &bytes[..]).unwrap(); }
+        let remote = self.remote(header.source);
+        self.senders[remote].send(bytes).ok();
+        self.grant(header);
+        self.stack.pop()
+    }
 }
 
-impl<T:Columnar+'static> Pullable<T> for BinaryPullable<T> {
+impl<T:Columnar+Send+'static> Pullable<T> for BinaryPullable<T> {
     #[inline]
     fn pull(&mut self) -> Option<T> {
         if let Some(data) = self.inner.pull() { Some(data) }
-        else if let Some(bytes) = self.receiver.try_recv().ok() {
-            self.stack.decode(&mut &bytes[..]).unwrap();
-            self.senders[0].send(bytes).unwrap();                   // TODO : Not clear where bytes came from; find out!
-            self.stack.pop()
-        }
+        else if let Some((header, bytes)) = self.receiver.try_recv().ok() { self.decode(header, bytes) }
         else { None }
     }
+
+    fn pull_all(&mut self, buffer: &mut Vec<T>) {
+        self.inner.pull_all(buffer);
+        while let Some((header, bytes)) = self.receiver.try_recv().ok() {
+            if let Some(data) = self.decode(header, bytes) { buffer.push(data); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use communication::{Pushable, Pullable};
+    use super::{Communicator, ThreadCommunicator, ProcessCommunicator, LoopbackCommunicator};
+
+    #[test]
+    fn thread_channels_are_fifo() {
+        let (mut pushers, mut pullable) = ThreadCommunicator.new_channel::<u64>("test");
+        for message in (0..10) { pushers[0].push(message); }
+        assert_eq!((0..10).map(|_| pullable.pull().unwrap()).collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert_eq!(pullable.pull(), None);
+    }
+
+    #[test]
+    fn process_channels_drain_in_order() {
+        let mut communicators = ProcessCommunicator::new_vector(2);
+        let (mut pushers0, _) = communicators[0].new_channel::<u64>("test");
+        let (mut pushers1, mut pullable1) = communicators[1].new_channel::<u64>("test");
+        for message in (0..5) { pushers0[1].push(message); }
+        pushers1[1].push(5);
+
+        let mut buffer = vec![];
+        pullable1.pull_all(&mut buffer);
+        assert_eq!(buffer, (0..6).collect::<Vec<_>>());
+        pullable1.pull_all(&mut buffer);
+        assert_eq!(buffer.len(), 6);
+    }
+
+    #[test]
+    fn process_pushes_wake_parked_workers() {
+        use std::thread;
+        use time;
+
+        // channel handles stay with their thread, so the pushing worker allocates its channel there.
+        let mut communicators = ProcessCommunicator::new_vector(2);
+        let mut communicator1 = communicators.pop().unwrap();
+        let (_, mut pullable0) = communicators[0].new_channel::<u64>("test");
+        let signal = communicators[0].signal().unwrap();
+
+        let start = time::precise_time_s();
+        let guard = thread::spawn(move || {
+            let (mut pushers1, _) = communicator1.new_channel::<u64>("test");
+            thread::sleep_ms(10);
+            pushers1[0].push(7);
+        });
+        signal.wait(10000);
+        assert!(time::precise_time_s() - start < 5.0);
+        assert_eq!(pullable0.pull(), Some(7));
+        guard.join().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "workers must build the same dataflow")]
+    fn divergent_allocations_are_reported() {
+        let mut communicators = ProcessCommunicator::new_vector(2);
+        communicators[0].new_channel::<u64>("exchange");
+        communicators[1].new_channel::<String>("exchange");
+    }
+
+    #[test]
+    fn loopback_roundtrips_messages() {
+        let mut communicators = LoopbackCommunicator::new_vector(2);
+        let (mut pushers0, mut pullable0) = communicators[0].new_channel::<(u64, Vec<String>)>("test");
+        let (mut pushers1, mut pullable1) = communicators[1].new_channel::<(u64, Vec<String>)>("test");
+
+        pushers0[1].push((3, vec!["three".to_string()]));
+        pushers1[1].push((4, vec![]));
+        pushers1[0].push((5, vec!["five".to_string(), "".to_string()]));
+
+        assert_eq!(pullable0.pull(), Some((5, vec!["five".to_string(), "".to_string()])));
+        assert_eq!(pullable0.pull(), None);
+        assert_eq!(pullable1.pull(), Some((3, vec!["three".to_string()])));
+        assert_eq!(pullable1.pull(), Some((4, vec![])));
+        assert_eq!(pullable1.pull(), None);
+    }
 }
@@ -23,6 +23,7 @@ impl<T: Timestamp, D: Data> Observer for OutputPort<T, D> {
     #[inline(always)] fn open(&mut self, time: &T) { for target in self.shared.borrow_mut().iter_mut() { target.open(time); } }
     #[inline(always)] fn push(&mut self, data: &D) { for target in self.shared.borrow_mut().iter_mut() { target.push(data); } }
     #[inline(always)] fn shut(&mut self, time: &T) { for target in self.shared.borrow_mut().iter_mut() { target.shut(time); } }
+    #[inline(always)] fn congested(&self) -> bool { self.shared.borrow().iter().any(|target| target.congested()) }
 }
 
 impl<T: Timestamp, D: Data> OutputPort<T, D> {
@@ -55,6 +56,7 @@ impl<O: Observer> Observer for ObserverHelper<O> where O::Time : Timestamp {
         self.observer.shut(time);
         self.count = 0;
     }
+    #[inline(always)] fn congested(&self) -> bool { self.observer.congested() }
 }
 
 impl<O: Observer> ObserverHelper<O> where O::Time : Eq+Clone+'static {
@@ -0,0 +1,215 @@
+use std::any::Any;
+use std::thread;
+
+use columnar::Columnar;
+use communication::{Communicator, Pushable, Pullable, PushHandle, PullHandle};
+use networking::Failure;
+use progress::subgraph::PARK_TIMEOUT_MS;
+
+// Collective operations among all workers, for coordination outside of dataflows: combining values from each worker
+// (allreduce), distributing a value from one worker (broadcast), and collecting values at one worker (gather).
+//
+// like channels, collectives rely on every worker doing the same thing: each must create its Collectives at the same
+// point in its channel allocation, and then start the same operations in the same order. operations are numbered as
+// they are started, so that messages from workers running ahead are held until the operation they belong to.
+//
+// each operation has a non-blocking form, start_*, which returns a Request to test until complete, and a blocking
+// form, which waits for completion. the blocking forms need every worker to be running concurrently, so when workers
+// share a thread (for example, in a Simulation), use the non-blocking forms and step the workers between tests. they
+// take the worker's communicator, to park on its signal while waiting and to give up should it report a failure.
+pub struct Collectives<T> {
+    index:      u64,                                // index of this worker
+    peers:      u64,                                // number of workers
+    pushers:    Vec<PushHandle<(u64, u64, T)>>,     // (operation, source, value) to each worker
+    puller:     PullHandle<(u64, u64, T)>,
+    received:   Vec<(u64, u64, T)>,                 // contributions to operations not yet completed
+    started:    u64,                                // operations started by this worker
+}
+
+// an operation in progress: its number, and the workers whose contributions it awaits.
+pub struct Request {
+    operation:  u64,
+    sources:    Vec<u64>,
+}
+
+impl<T:Send+Columnar+Any> Collectives<T> {
+    pub fn new<C: Communicator>(communicator: &mut C) -> Collectives<T> {
+        let (pushers, puller) = communicator.new_channel("Collectives");
+        Collectives {
+            index:      communicator.index(),
+            peers:      communicator.peers(),
+            pushers:    pushers,
+            puller:     puller,
+            received:   Vec::new(),
+            started:    0,
+        }
+    }
+
+    // sends value to each of targets, and returns a request for the contributions of sources.
+    fn start(&mut self, value: Option<T>, targets: Vec<u64>, sources: Vec<u64>) -> Request where T: Clone {
+        let operation = self.started;
+        self.started += 1;
+        if let Some(value) = value {
+            for &target in targets.iter() {
+                self.pushers[target as usize].push((operation, self.index, value.clone()));
+            }
+        }
+        Request { operation: operation, sources: sources }
+    }
+
+    // starts combining the values of all workers; the request completes with each worker's value, in worker order.
+    pub fn start_allreduce(&mut self, value: T) -> Request where T: Clone {
+        let everyone: Vec<u64> = (0..self.peers).collect();
+        self.start(Some(value), everyone.clone(), everyone)
+    }
+
+    // starts sending the value of root, which should pass Some(value), to all workers; the request completes with it.
+    pub fn start_broadcast(&mut self, root: u64, value: Option<T>) -> Request where T: Clone {
+        let value = if self.index == root {
+            Some(value.expect("broadcast: the root must supply a value"))
+        }
+        else { None };
+        let everyone = (0..self.peers).collect();
+        self.start(value, everyone, vec![root])
+    }
+
+    // starts collecting the value of each worker at root; at root the request completes with each worker's value, in
+    // worker order, and at other workers it completes at once with no values.
+    pub fn start_gather(&mut self, root: u64, value: T) -> Request where T: Clone {
+        let sources = if self.index == root { (0..self.peers).collect() } else { Vec::new() };
+        self.start(Some(value), vec![root], sources)
+    }
+
+    // the contributions to a request in the order of its sources, if they have all arrived.
+    pub fn test(&mut self, request: &Request) -> Option<Vec<T>> {
+        self.puller.pull_all(&mut self.received);
+
+        let operation = request.operation;
+        let arrived = request.sources.iter().all(|&source| {
+            self.received.iter().any(|&(o, s, _)| o == operation && s == source)
+        });
+        if !arrived { return None; }
+
+        let mut result = Vec::with_capacity(request.sources.len());
+        for &source in request.sources.iter() {
+            let position = self.received.iter().position(|&(o, s, _)| o == operation && s == source).unwrap();
+            result.push(self.received.remove(position).2);
+        }
+        Some(result)
+    }
+
+    // blocks until the contributions to a request have all arrived, or the communicator reports a failure, in which
+    // case they may never arrive.
+    pub fn wait<C: Communicator>(&mut self, communicator: &C, request: &Request) -> Result<Vec<T>, Failure> {
+        loop {
+            if let Some(result) = self.test(request) { return Ok(result); }
+            if let Some(failure) = communicator.failure() { return Err(failure); }
+            match communicator.signal() {
+                Some(signal) => signal.wait(PARK_TIMEOUT_MS),
+                None         => thread::yield_now(),
+            }
+        }
+    }
+
+    // combines the values of all workers with op, folding them in worker order so that all workers get the same result.
+    pub fn allreduce<C: Communicator, F: Fn(T, T)->T>(&mut self, communicator: &C, value: T, op: F) -> Result<T, Failure> where T: Clone {
+        let request = self.start_allreduce(value);
+        let mut values = try!(self.wait(communicator, &request)).into_iter();
+        let first = values.next().unwrap();
+        Ok(values.fold(first, |x, y| op(x, y)))
+    }
+
+    // the value of root, which should pass Some(value), at all workers.
+    pub fn broadcast<C: Communicator>(&mut self, communicator: &C, root: u64, value: Option<T>) -> Result<T, Failure> where T: Clone {
+        let request = self.start_broadcast(root, value);
+        Ok(try!(self.wait(communicator, &request)).pop().unwrap())
+    }
+
+    // the value of each worker, in worker order, at root; None at other workers.
+    pub fn gather<C: Communicator>(&mut self, communicator: &C, root: u64, value: T) -> Result<Option<Vec<T>>, Failure> where T: Clone {
+        let request = self.start_gather(root, value);
+        let values = try!(self.wait(communicator, &request));
+        Ok(if self.index == root { Some(values) } else { None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::thread;
+
+    use columnar::Columnar;
+    use communication::{Communicator, PushHandle, PullHandle};
+    use networking::Failure;
+
+    use super::{Collectives, Request};
+
+    #[test]
+    fn collectives_agree_across_threads() {
+        use communication::ProcessCommunicator;
+
+        let mut guards = Vec::new();
+        for mut communicator in ProcessCommunicator::new_vector(4).into_iter() {
+            guards.push(thread::scoped(move || {
+                let index = communicator.index();
+                let mut collectives = Collectives::<u64>::new(&mut communicator);
+
+                // repeated operations, so that workers running ahead deliver early contributions
+                for round in (0..10) {
+                    assert_eq!(collectives.allreduce(&communicator, index + round, |x, y| x + y).unwrap(), 6 + 4 * round);
+                    assert_eq!(collectives.allreduce(&communicator, index, |x, y| if x > y { x } else { y }).unwrap(), 3);
+                    assert_eq!(collectives.broadcast(&communicator, round % 4, if index == round % 4 { Some(round) } else { None }).unwrap(), round);
+                    let gathered = collectives.gather(&communicator, 0, index * round).unwrap();
+                    if index == 0 { assert_eq!(gathered, Some(vec![0, round, 2 * round, 3 * round])); }
+                    else          { assert_eq!(gathered, None); }
+                }
+            }));
+        }
+    }
+
+    #[test]
+    fn collectives_complete_without_blocking() {
+        use communication::ProcessCommunicator;
+
+        // all workers share this thread, so each must start an operation before any can complete it
+        let mut communicators = ProcessCommunicator::new_vector(3);
+        let mut collectives: Vec<Collectives<String>> = communicators.iter_mut().map(|c| Collectives::new(c)).collect();
+
+        let first = collectives[0].start_allreduce(format!("a"));
+        let second = collectives[1].start_allreduce(format!("b"));
+        assert_eq!(collectives[0].test(&first), None);
+        let third = collectives[2].start_allreduce(format!("c"));
+
+        let expected = Some(vec![format!("a"), format!("b"), format!("c")]);
+        assert_eq!(collectives[0].test(&first), expected);
+        assert_eq!(collectives[1].test(&second), expected);
+        assert_eq!(collectives[2].test(&third), expected);
+
+        // workers other than the root complete a gather at once
+        let requests: Vec<Request> = (0..3).map(|i| collectives[i].start_gather(1, format!("worker {}", i))).collect();
+        assert_eq!(collectives[0].test(&requests[0]), Some(vec![]));
+        assert_eq!(collectives[1].test(&requests[1]), Some(vec![format!("worker 0"), format!("worker 1"), format!("worker 2")]));
+    }
+
+    #[test]
+    fn collectives_give_up_on_failure() {
+        use communication::{ProcessCommunicator, Signal};
+        use networking::PeerFailure;
+
+        // a worker whose peers have failed, and so will never contribute.
+        struct Failed(ProcessCommunicator);
+        impl Communicator for Failed {
+            fn index(&self) -> u64 { self.0.index() }
+            fn peers(&self) -> u64 { self.0.peers() }
+            fn new_channel<D:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<D>>, PullHandle<D>) { self.0.new_channel(name) }
+            fn failure(&self) -> Option<Failure> { Some(Failure::Peer(PeerFailure { process: 1, reason: format!("gone") })) }
+            fn signal(&self) -> Option<Signal> { self.0.signal() }
+        }
+
+        let mut communicator = Failed(ProcessCommunicator::new_vector(2).swap_remove(0));
+        let mut collectives = Collectives::<u64>::new(&mut communicator);
+        assert!(collectives.allreduce(&communicator, 1, |x, y| x + y).is_err());
+        assert!(collectives.broadcast(&communicator, 1, None).is_err());
+        assert!(collectives.gather(&communicator, 0, 1).is_err());
+    }
+}
@@ -1,10 +1,11 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use core::marker::PhantomData;
 
 use progress::Timestamp;
 use communication::Data;
-use communication::{Communicator, Pullable, Pushable, PushableObserver, Observer};
+use communication::{Communicator, Pullable, PushHandle, PullHandle, PushableObserver, Observer};
 use communication::observer::ExchangeObserver;
 
 use columnar::Columnar;
@@ -13,17 +14,18 @@ use columnar::Columnar;
 pub trait ParallelizationContract<T: Timestamp, D: Data> {
     type Observer: Observer<Time=T, Data=D>+'static;
     type Pullable: Pullable<(T, Vec<D>)>+'static;
-    fn connect<C: Communicator>(self, communicator: &mut C) -> (Self::Observer, Self::Pullable);
+    // name identifies the operator the channel is for, so that workers connecting different operators are reported.
+    fn connect<C: Communicator>(self, communicator: &mut C, name: &str) -> (Self::Observer, Self::Pullable);
 }
 
 // direct connection
 pub struct Pipeline;
 impl<T: Timestamp, D: Data> ParallelizationContract<T, D> for Pipeline {
-    type Observer = (Vec<D>, Rc<RefCell<Vec<(T, Vec<D>)>>>);
-    type Pullable = Rc<RefCell<Vec<(T, Vec<D>)>>>;
-    fn connect<C: Communicator>(self,_communicator: &mut C) -> (<Pipeline as ParallelizationContract<T, D>>::Observer,
-                                                                <Pipeline as ParallelizationContract<T, D>>::Pullable) {
-        let shared = Rc::new(RefCell::new(Vec::new()));
+    type Observer = (Vec<D>, Rc<RefCell<VecDeque<(T, Vec<D>)>>>);
+    type Pullable = Rc<RefCell<VecDeque<(T, Vec<D>)>>>;
+    fn connect<C: Communicator>(self,_communicator: &mut C, _name: &str) -> (<Pipeline as ParallelizationContract<T, D>>::Observer,
+                                                                             <Pipeline as ParallelizationContract<T, D>>::Pullable) {
+        let shared = Rc::new(RefCell::new(VecDeque::new()));
         return ((Vec::new(), shared.clone()), shared.clone());
     }
 }
@@ -40,11 +42,11 @@ impl<D, F: Fn(&D)->u64> Exchange<D, F> {
 }
 
 impl<T: Timestamp, D: Data+Columnar, F: Fn(&D)->u64+'static> ParallelizationContract<T, D> for Exchange<D, F> {
-    type Observer = ExchangeObserver<PushableObserver<T,D,Box<Pushable<(T,Vec<D>)>>>, F>;
-    type Pullable = Box<Pullable<(T, Vec<D>)>>;
-    fn connect<C: Communicator>(self, communicator: &mut C) -> (<Exchange<D, F> as ParallelizationContract<T, D>>::Observer,
-                                                                <Exchange<D, F> as ParallelizationContract<T, D>>::Pullable) {
-        let (senders, receiver) = communicator.new_channel();
+    type Observer = ExchangeObserver<PushableObserver<T,D,PushHandle<(T,Vec<D>)>>, F>;
+    type Pullable = PullHandle<(T, Vec<D>)>;
+    fn connect<C: Communicator>(self, communicator: &mut C, name: &str) -> (<Exchange<D, F> as ParallelizationContract<T, D>>::Observer,
+                                                                            <Exchange<D, F> as ParallelizationContract<T, D>>::Pullable) {
+        let (senders, receiver) = communicator.new_channel(name);
 
         let exchange_sender = ExchangeObserver {
             observers:  senders.into_iter().map(|x| PushableObserver { data: Vec::new(), pushable: x, phantom: PhantomData }).collect(),
@@ -1,14 +1,21 @@
 pub use communication::channels::Data;
 pub use communication::allocator::ThreadCommunicator;
 pub use communication::allocator::ProcessCommunicator;
+pub use communication::allocator::LoopbackCommunicator;
 pub use communication::allocator::BinaryCommunicator;
+pub use communication::simulation::{Simulation, SimulationCommunicator};
+pub use communication::collectives::{Collectives, Request};
+pub use communication::signal::Signal;
 pub use communication::exchange::ParallelizationContract;
 pub use communication::observer::Observer;
-pub use communication::allocator::{Communicator};
-pub use communication::pushpull::{Pushable, Pullable, PushableObserver};
+pub use communication::allocator::{Communicator, ChannelTag};
+pub use communication::pushpull::{Pushable, Pullable, PushableObserver, PushHandle, PullHandle};
 
 pub mod channels;
 pub mod allocator;
 pub mod exchange;
 pub mod observer;
 pub mod pushpull;
+pub mod simulation;
+pub mod collectives;
+pub mod signal;
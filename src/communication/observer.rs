@@ -2,6 +2,7 @@ use std::mem;
 use std::sync::mpsc::Sender;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 
 use communication::Pushable;
 
@@ -16,6 +17,7 @@ pub trait Observer {
     fn open(&mut self, time: &Self::Time);   // new punctuation, essentially ...
     fn push(&mut self, time: &Self::Data);   // reveals push data to the observer.
     fn shut(&mut self, time: &Self::Time);   // indicates that we are done for now.
+    fn congested(&self) -> bool { false }    // data pushed now would wait on a slow receiver (see Pushable).
 }
 
 // extension trait for creating an RAII observer session from any observer
@@ -44,13 +46,13 @@ impl<'a, O:Observer+'a> ObserverSession<'a, O> where <O as Observer>::Time : 'a
     #[inline(always)] pub fn push(&mut self, data: &O::Data) { self.observer.push(data); }
 }
 
-// implementation for intra-thread queues (a Vec<D>)
-impl<T:Clone+'static, D:Clone+'static> Observer for (Vec<D>, Rc<RefCell<Vec<(T, Vec<D>)>>>) {
+// implementation for intra-thread queues (a VecDeque<(T, Vec<D>)>, pulled from the front)
+impl<T:Clone+'static, D:Clone+'static> Observer for (Vec<D>, Rc<RefCell<VecDeque<(T, Vec<D>)>>>) {
     type Time = T;
     type Data = D;
     #[inline(always)] fn open(&mut self,_time: &T) { }
     #[inline(always)] fn push(&mut self, data: &D) { self.0.push(data.clone()); }
-    #[inline(always)] fn shut(&mut self, time: &T) { let vec = mem::replace(&mut self.0, Vec::new()); self.1.borrow_mut().push((time.clone(), vec)); }
+    #[inline(always)] fn shut(&mut self, time: &T) { let vec = mem::replace(&mut self.0, Vec::new()); self.1.borrow_mut().push_back((time.clone(), vec)); }
 }
 
 // implementation for inter-thread queues
@@ -74,6 +76,7 @@ impl<O: Observer> Observer for BroadcastObserver<O> {
     #[inline(always)] fn open(&mut self, time: &O::Time) { for observer in self.observers.iter_mut() { observer.open(time); } }
     #[inline(always)] fn push(&mut self, data: &O::Data) { for observer in self.observers.iter_mut() { observer.push(data); } }
     #[inline(always)] fn shut(&mut self, time: &O::Time) { for observer in self.observers.iter_mut() { observer.shut(time); } }
+    #[inline(always)] fn congested(&self) -> bool { self.observers.iter().any(|observer| observer.congested()) }
 }
 
 // an observer routing between many observers
@@ -91,6 +94,7 @@ impl<O: Observer, H: Fn(&O::Data) -> u64+'static> Observer for ExchangeObserver<
         self.observers[dst as usize].push(data);
     }
     #[inline(always)] fn shut(&mut self, time: &O::Time) -> () { for observer in self.observers.iter_mut() { observer.shut(time); } }
+    #[inline(always)] fn congested(&self) -> bool { self.observers.iter().any(|observer| observer.congested()) }
 }
 
 // an observer buffering records before sending
@@ -116,6 +120,7 @@ impl<D: Clone+'static, O: Observer<Data = Vec<D>>> Observer for BufferedObserver
         self.observer.shut(time);
         self.buffer.clear();
     }
+    #[inline(always)] fn congested(&self) -> bool { self.observer.congested() }
 }
 
 // dual to BufferedObserver, flattens out buffers
@@ -129,6 +134,7 @@ impl<O: Observer> Observer for FlattenedObserver<O> {
     #[inline(always)] fn open(&mut self, time: &O::Time) -> () { self.observer.open(time); }
     #[inline(always)] fn push(&mut self, data: &Vec<O::Data>) -> () { for datum in data.iter() { self.observer.push(datum); } }
     #[inline(always)] fn shut(&mut self, time: &O::Time) -> () { self.observer.shut(time); }
+    #[inline(always)] fn congested(&self) -> bool { self.observer.congested() }
 }
 
 
@@ -162,4 +168,11 @@ impl<T, D, O1: Observer<Time=T, Data=D>, O2: Observer<Time=T, Data=D>> Observer
             ObserverPair::Type2(ref mut observer) => observer.shut(time),
         }
     }
+    #[inline(always)]
+    fn congested(&self) -> bool {
+        match *self {
+            ObserverPair::Type1(ref observer) => observer.congested(),
+            ObserverPair::Type2(ref observer) => observer.congested(),
+        }
+    }
 }
@@ -1,23 +1,109 @@
 use std::mem;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{Sender, Receiver};
 use core::marker::PhantomData;
 
-use communication::Observer;
+use communication::{Observer, Signal};
 
 
-pub trait Pushable<T> { fn push(&mut self, data: T); }        // like observer
-pub trait Pullable<T> { fn pull(&mut self) -> Option<T>; }    // like iterator
+// Pushables and Pullables are the ends of channels handed out by Communicators. a Pullable yields the messages
+// pushed by each sender in the order they were pushed; messages from different senders may interleave arbitrarily.
+// every communicator provides this, except a Simulation asked to reorder messages.
+pub trait Pushable<T> {                                         // like observer
+    fn push(&mut self, data: T);
 
-impl<T:'static> Pushable<T> for Rc<RefCell<Vec<T>>> { fn push(&mut self, data: T) { self.borrow_mut().push(data); } }
-impl<T:'static> Pullable<T> for Rc<RefCell<Vec<T>>> { fn pull(&mut self) -> Option<T> { self.borrow_mut().pop() } }
+    // true while messages pushed would only wait for the receiver to catch up, rather than be sent. pushing anyway
+    // is not an error, but producers able to wait (e.g. an operator with input left) should hold off until false.
+    fn congested(&self) -> bool { false }
+}
+pub trait Pullable<T> {                                         // like iterator
+    fn pull(&mut self) -> Option<T>;
+
+    // appends every available message to buffer, in the order pull would return them. channels shared with
+    // other threads override this to synchronize once for all of the messages, rather than once for each.
+    fn pull_all(&mut self, buffer: &mut Vec<T>) { while let Some(data) = self.pull() { buffer.push(data); } }
+}
+
+impl<T:'static> Pushable<T> for Rc<RefCell<VecDeque<T>>> { fn push(&mut self, data: T) { self.borrow_mut().push_back(data); } }
+impl<T:'static> Pullable<T> for Rc<RefCell<VecDeque<T>>> {
+    fn pull(&mut self) -> Option<T> { self.borrow_mut().pop_front() }
+    fn pull_all(&mut self, buffer: &mut Vec<T>) { buffer.extend(self.borrow_mut().drain()); }
+}
+
+impl<T:Send+'static> Pushable<T> for Arc<Mutex<VecDeque<T>>> { fn push(&mut self, data: T) { self.lock().ok().expect("mutex error?").push_back(data); } }
+impl<T:Send+'static> Pullable<T> for Arc<Mutex<VecDeque<T>>> {
+    fn pull(&mut self) -> Option<T> { self.lock().ok().expect("mutex error?").pop_front() }
+    fn pull_all(&mut self, buffer: &mut Vec<T>) { buffer.extend(self.lock().ok().expect("mutex error?").drain()); }
+}
 
+// Receiver has no way to take more than one message at a time, so pull_all costs a try_recv per message.
 impl<T:Send+'static> Pushable<T> for Sender<T> { fn push(&mut self, data: T) { self.send(data).ok().expect("send error"); } }
 impl<T:Send+'static> Pullable<T> for Receiver<T> { fn pull(&mut self) -> Option<T> { self.try_recv().ok() }}
 
-impl<T:Send> Pushable<T> for Box<Pushable<T>> { fn push(&mut self, data: T) { (**self).push(data); } }
-impl<T:Send> Pullable<T> for Box<Pullable<T>> { fn pull(&mut self) -> Option<T> { (**self).pull() } }
+impl<T:Send> Pushable<T> for Box<Pushable<T>> {
+    fn push(&mut self, data: T) { (**self).push(data); }
+    fn congested(&self) -> bool { (**self).congested() }
+}
+impl<T:Send> Pullable<T> for Box<Pullable<T>> {
+    fn pull(&mut self) -> Option<T> { (**self).pull() }
+    fn pull_all(&mut self, buffer: &mut Vec<T>) { (**self).pull_all(buffer) }
+}
+
+// The ends of a channel as handed out by Communicator::new_channel. channels of the ThreadCommunicator and the
+// ProcessCommunicator are variants, so that pushing to and pulling from them are static calls; the channels of
+// other communicators are boxed, and dispatched dynamically. pushes to another thread notify its worker's signal.
+pub enum PushHandle<T> {
+    Thread(Rc<RefCell<VecDeque<T>>>),
+    Process(Arc<Mutex<VecDeque<T>>>, Signal),
+    Boxed(Box<Pushable<T>>),
+}
+
+impl<T:Send+'static> Pushable<T> for PushHandle<T> {
+    #[inline(always)]
+    fn push(&mut self, data: T) {
+        match *self {
+            PushHandle::Thread(ref mut queue)   => queue.push(data),
+            PushHandle::Process(ref mut queue, ref signal) => { queue.push(data); signal.notify(); },
+            PushHandle::Boxed(ref mut boxed)    => boxed.push(data),
+        }
+    }
+    // queues within a process are unbounded; only boxed (e.g. networked) channels push back.
+    #[inline(always)]
+    fn congested(&self) -> bool {
+        match *self {
+            PushHandle::Boxed(ref boxed)    => boxed.congested(),
+            _                               => false,
+        }
+    }
+}
+
+pub enum PullHandle<T> {
+    Thread(Rc<RefCell<VecDeque<T>>>),
+    Process(Arc<Mutex<VecDeque<T>>>),
+    Boxed(Box<Pullable<T>>),
+}
+
+impl<T:Send+'static> Pullable<T> for PullHandle<T> {
+    #[inline(always)]
+    fn pull(&mut self) -> Option<T> {
+        match *self {
+            PullHandle::Thread(ref mut queue)   => queue.pull(),
+            PullHandle::Process(ref mut queue)  => queue.pull(),
+            PullHandle::Boxed(ref mut boxed)    => boxed.pull(),
+        }
+    }
+    #[inline(always)]
+    fn pull_all(&mut self, buffer: &mut Vec<T>) {
+        match *self {
+            PullHandle::Thread(ref mut queue)   => queue.pull_all(buffer),
+            PullHandle::Process(ref mut queue)  => queue.pull_all(buffer),
+            PullHandle::Boxed(ref mut boxed)    => boxed.pull_all(buffer),
+        }
+    }
+}
 
 pub struct PushableObserver<T:Send, D:Send+Clone, P: Pushable<(T, Vec<D>)>> {
     pub data:       Vec<D>,
@@ -35,4 +121,5 @@ impl<T:Send+Clone, D:Send+Clone, P: Pushable<(T, Vec<D>)>> Observer for Pushable
             self.pushable.push((time.clone(), mem::replace(&mut self.data, Vec::new())));
         }
     }
+    #[inline(always)] fn congested(&self) -> bool { self.pushable.congested() }
 }
@@ -0,0 +1,66 @@
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Wakes a worker parked for lack of work. Whatever delivers work to a worker from another thread (channel pushes
+// from other workers, networking threads, inputs fed from outside the worker) should notify the worker's signal once
+// the work is in place. a notification while the worker is not parked is kept, so that its next wait returns at once.
+#[derive(Clone)]
+pub struct Signal {
+    inner:  Arc<(AtomicBool, Mutex<()>, Condvar)>,  // (notified, lock for waiting, parked worker)
+}
+
+impl Signal {
+    pub fn new() -> Signal {
+        Signal { inner: Arc::new((AtomicBool::new(false), Mutex::new(()), Condvar::new())) }
+    }
+
+    pub fn notify(&self) {
+        let (ref notified, ref lock, ref condvar) = *self.inner;
+        // only the first notification since the last wait needs to wake the worker
+        if !notified.swap(true, Ordering::SeqCst) {
+            let _guard = lock.lock().ok().expect("mutex error?");
+            condvar.notify_one();
+        }
+    }
+
+    // blocks until notified, or for at most timeout_ms, and clears any notification. the work a notification
+    // announces is in place before it is sent, so it is visible to the worker once wait returns.
+    pub fn wait(&self, timeout_ms: u32) {
+        let (ref notified, ref lock, ref condvar) = *self.inner;
+        let guard = lock.lock().ok().expect("mutex error?");
+        if !notified.swap(false, Ordering::SeqCst) {
+            let _ = condvar.wait_timeout_ms(guard, timeout_ms);
+            notified.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Signal;
+
+    #[test]
+    fn signals_wake_waiting_workers() {
+        use std::thread;
+        use time;
+
+        // a notification before the wait is kept
+        let signal = Signal::new();
+        let start = time::precise_time_s();
+        signal.notify();
+        signal.wait(10000);
+        assert!(time::precise_time_s() - start < 5.0);
+
+        // a notification from another thread wakes a waiting one
+        let notifier = signal.clone();
+        let guard = thread::spawn(move || { thread::sleep_ms(10); notifier.notify(); });
+        signal.wait(10000);
+        assert!(time::precise_time_s() - start < 5.0);
+        guard.join().unwrap();
+
+        // and otherwise a wait lasts until its timeout
+        let start = time::precise_time_s();
+        signal.wait(10);
+        assert!(time::precise_time_s() - start >= 0.005);
+    }
+}
@@ -0,0 +1,299 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::any::Any;
+use std::cmp::max;
+
+use columnar::Columnar;
+use communication::{Communicator, ChannelTag, Pushable, Pullable, PushHandle, PullHandle};
+
+// A deterministic stand-in for ProcessCommunicator, for reproducing multi-worker bugs: logical workers share one
+// thread, are stepped in an order drawn from a seeded random number generator, and exchange messages over channels
+// that delay, batch, and reorder them as the generator directs. Running with the same seed replays the same schedule.
+//
+// time is measured in steps of the simulation. each message is due a random number of steps after it is sent, up to
+// the maximum delay, and is delivered only once due; messages that fall due together arrive together. batching gives
+// all messages sent from one worker to another in the same step the same due step. without reordering, messages
+// between each pair of workers are delivered in the order they were sent, and with it in a random order.
+pub struct Simulation {
+    state:      Rc<RefCell<SimulationState>>,
+    workers:    u64,
+}
+
+struct SimulationState {
+    random:     XorShift,
+    now:        u64,    // steps taken so far
+    delay:      u64,    // maximum steps a message is held in flight
+    reorder:    bool,   // deliver due messages in a random order, rather than the order they were sent
+    batch:      bool,   // messages sent between two workers in the same step are due in the same step
+}
+
+// a small xorshift generator, so that what is drawn from a seed is repeatable.
+#[derive(Copy, Clone, Debug)]
+pub struct XorShift(u64);
+impl XorShift {
+    pub fn new(seed: u64) -> XorShift { XorShift(if seed == 0 { 0x2545F4914F6CDD1D } else { seed }) }   // xorshift is stuck at zero
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+impl Simulation {
+    pub fn new(workers: u64, seed: u64) -> Simulation {
+        Simulation {
+            state:      Rc::new(RefCell::new(SimulationState {
+                random:     XorShift::new(seed),
+                now:        0,
+                delay:      0,
+                reorder:    false,
+                batch:      false,
+            })),
+            workers:    workers,
+        }
+    }
+
+    pub fn delay(self, steps: u64) -> Simulation { self.state.borrow_mut().delay = steps; self }
+    pub fn reorder(self, reorder: bool) -> Simulation { self.state.borrow_mut().reorder = reorder; self }
+    pub fn batch(self, batch: bool) -> Simulation { self.state.borrow_mut().batch = batch; self }
+
+    // a communicator for each logical worker.
+    pub fn communicators(&self) -> Vec<SimulationCommunicator> {
+        let channels = Rc::new(RefCell::new(Vec::new()));
+        (0..self.workers).map(|index| SimulationCommunicator {
+            index:      index,
+            peers:      self.workers,
+            allocated:  0,
+            channels:   channels.clone(),
+            state:      self.state.clone(),
+        }).collect()
+    }
+
+    // repeatedly steps a randomly chosen worker, by calling `step` with its index, until each has returned false.
+    // returns the number of steps taken.
+    pub fn run<F: FnMut(u64)->bool>(&self, mut step: F) -> u64 {
+        let mut active: Vec<u64> = (0..self.workers).collect();
+        let start = self.state.borrow().now;
+        while active.len() > 0 {
+            let position = {
+                let mut state = self.state.borrow_mut();
+                state.now += 1;
+                (state.random.next() % active.len() as u64) as usize
+            };
+            if !step(active[position]) { active.remove(position); }
+        }
+        self.state.borrow().now - start
+    }
+}
+
+// A Communicator for one logical worker of a Simulation.
+pub struct SimulationCommunicator {
+    index:      u64,
+    peers:      u64,
+    allocated:  u64,                                            // channels allocated by this worker
+    channels:   Rc<RefCell<Vec<(u64, ChannelTag, Box<Any>)>>>,  // (first allocator, its tag, in-flight queues)
+                                                                // Box<Any> -> Box<Vec<Rc<RefCell<InFlight<T>>>>>
+    state:      Rc<RefCell<SimulationState>>,
+}
+
+impl Communicator for SimulationCommunicator {
+    fn index(&self) -> u64 { self.index }
+    fn peers(&self) -> u64 { self.peers }
+    fn new_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        let tag = ChannelTag::new::<T>(name);
+        let mut channels = self.channels.borrow_mut();
+        if self.allocated == channels.len() as u64 {
+            let queues: Vec<Rc<RefCell<InFlight<T>>>> = (0..self.peers).map(|_| Rc::new(RefCell::new(InFlight::new(self.peers)))).collect();
+            channels.push((self.index, tag.clone(), Box::new(queues)));
+        }
+
+        let entry = &mut channels[self.allocated as usize];
+        if entry.1 != tag {
+            panic!("worker {}: channel {} allocated for {}, but by worker {} for {}; workers must build the same dataflow",
+                   self.index, self.allocated, tag, entry.0, entry.1);
+        }
+        self.allocated += 1;
+
+        match entry.2.downcast_ref::<Vec<Rc<RefCell<InFlight<T>>>>>() {
+            Some(queues) => {
+                let pushers = queues.iter().map(|queue| PushHandle::Boxed(Box::new(SimulatedPushable {
+                    source:     self.index,
+                    queue:      queue.clone(),
+                    state:      self.state.clone(),
+                }) as Box<Pushable<T>>)).collect();
+                let pullable = PullHandle::Boxed(Box::new(SimulatedPullable {
+                    queue:      queues[self.index as usize].clone(),
+                    state:      self.state.clone(),
+                }) as Box<Pullable<T>>);
+                (pushers, pullable)
+            }
+            None => { panic!("unable to cast channel correctly"); }
+        }
+    }
+}
+
+// messages in flight to one worker on one channel.
+struct InFlight<T> {
+    messages:   Vec<(u64, u64, T)>,     // (due step, sequence number, message)
+    sequence:   u64,                    // messages sent so far
+    last:       Vec<(u64, u64)>,        // (sent step, due step) of the latest message from each source
+}
+
+impl<T> InFlight<T> {
+    fn new(sources: u64) -> InFlight<T> {
+        InFlight { messages: Vec::new(), sequence: 0, last: vec![(0, 0); sources as usize] }
+    }
+}
+
+struct SimulatedPushable<T> {
+    source:     u64,
+    queue:      Rc<RefCell<InFlight<T>>>,
+    state:      Rc<RefCell<SimulationState>>,
+}
+
+impl<T:'static> Pushable<T> for SimulatedPushable<T> {
+    fn push(&mut self, data: T) {
+        let mut state = self.state.borrow_mut();
+        let mut queue = self.queue.borrow_mut();
+
+        let (sent, due) = queue.last[self.source as usize];
+        let delay = state.random.next() % (state.delay + 1);
+        let mut next = state.now + delay;
+        if state.batch && sent == state.now { next = due; }
+        if !state.reorder { next = max(next, due); }     // not before the previous message, so not overtaking it
+
+        queue.last[self.source as usize] = (state.now, next);
+        let sequence = queue.sequence;
+        queue.sequence += 1;
+        queue.messages.push((next, sequence, data));
+    }
+}
+
+struct SimulatedPullable<T> {
+    queue:      Rc<RefCell<InFlight<T>>>,
+    state:      Rc<RefCell<SimulationState>>,
+}
+
+impl<T:'static> Pullable<T> for SimulatedPullable<T> {
+    fn pull(&mut self) -> Option<T> {
+        let mut state = self.state.borrow_mut();
+        let mut queue = self.queue.borrow_mut();
+
+        // among the messages that are due, a random one when reordering, and otherwise the first sent.
+        let due: Vec<usize> = (0..queue.messages.len()).filter(|&i| queue.messages[i].0 <= state.now).collect();
+        if due.len() == 0 { return None; }
+        let position = if state.reorder { due[(state.random.next() % due.len() as u64) as usize] }
+                       else { *due.iter().min_by(|&&i| queue.messages[i].1).unwrap() };
+
+        Some(queue.messages.swap_remove(position).2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use communication::{Communicator, Pushable, Pullable};
+
+    use super::Simulation;
+
+    #[test]
+    fn simulated_channels_deliver_everything_replayably() {
+        let deliveries = |seed: u64, reorder: bool| {
+            let simulation = Simulation::new(2, seed).delay(5).reorder(reorder).batch(true);
+            let mut communicators = simulation.communicators();
+            let (mut pushers, _) = communicators[0].new_channel::<u64>("test");
+            let (_, mut pullable) = communicators[1].new_channel::<u64>("test");
+
+            let mut sent = 0;
+            let mut received = Vec::new();
+            simulation.run(|index| {
+                if index == 0 && sent < 100 { pushers[1].push(sent); sent += 1; }
+                while let Some(message) = pullable.pull() { received.push(message); }
+                received.len() < 100
+            });
+            received
+        };
+
+        let expected: Vec<u64> = (0..100).collect();
+        assert_eq!(deliveries(7, false), expected);
+
+        let reordered = deliveries(7, true);
+        assert!(reordered != expected);
+        assert_eq!(reordered, deliveries(7, true));
+
+        let mut sorted = reordered.clone();
+        sorted.sort();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn simulated_workers_complete_a_barrier() {
+        use std::rc::Rc;
+        use std::cell::Cell;
+        use progress::{Graph, Scope, Antichain, CountMap};
+        use progress::subgraph::{new_graph, Summary};
+        use progress::subgraph::Source::ScopeOutput;
+        use progress::subgraph::Target::ScopeInput;
+        use example::barrier::BarrierScope;
+
+        const TTL: u64 = 100;
+
+        // a barrier sharing the epoch it has reached, to inspect once the simulation is done.
+        struct Observed { barrier: BarrierScope, epoch: Rc<Cell<u64>> }
+        impl Scope<((), u64)> for Observed {
+            fn name(&self) -> String { self.barrier.name() }
+            fn inputs(&self) -> u64 { self.barrier.inputs() }
+            fn outputs(&self) -> u64 { self.barrier.outputs() }
+            fn get_internal_summary(&mut self) -> (Vec<Vec<Antichain<Summary<(), u64>>>>, Vec<CountMap<((), u64)>>) {
+                self.barrier.get_internal_summary()
+            }
+            fn push_external_progress(&mut self, external: &mut Vec<CountMap<((), u64)>>) {
+                self.barrier.push_external_progress(external)
+            }
+            fn pull_internal_progress(&mut self, internal: &mut Vec<CountMap<((), u64)>>,
+                                                 consumed: &mut Vec<CountMap<((), u64)>>,
+                                                 produced: &mut Vec<CountMap<((), u64)>>) -> bool {
+                let result = self.barrier.pull_internal_progress(internal, consumed, produced);
+                self.epoch.set(self.barrier.epoch);
+                result
+            }
+            fn schedule_on_activation(&self) -> bool { self.barrier.schedule_on_activation() }
+        }
+
+        // the workers stepped, in order, and the epoch each barrier reached.
+        let run = |seed: u64| {
+            let simulation = Simulation::new(3, seed).delay(10).batch(true);
+            let mut graphs = Vec::new();
+            let mut epochs = Vec::new();
+            for communicator in simulation.communicators().into_iter() {
+                let mut graph = new_graph(communicator);
+                let peers = graph.communicator().peers();
+                let epoch = Rc::new(Cell::new(0));
+                graph.add_scope(Observed { barrier: BarrierScope { epoch: 0, ready: true, degree: peers, ttl: TTL }, epoch: epoch.clone() });
+                graph.connect(ScopeOutput(0, 0), ScopeInput(0, 0));
+                graph.0.borrow_mut().get_internal_summary();
+                graph.0.borrow_mut().set_external_summary(Vec::new(), &mut Vec::new());
+                graph.0.borrow_mut().push_external_progress(&mut Vec::new());
+                graphs.push(graph);
+                epochs.push(epoch);
+            }
+
+            let mut schedule = Vec::new();
+            simulation.run(|index| {
+                schedule.push(index);
+                graphs[index as usize].0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new())
+            });
+            (schedule, epochs.iter().map(|epoch| epoch.get()).collect::<Vec<_>>())
+        };
+
+        // every barrier went through each epoch up to its ttl, and released its last capability.
+        let (schedule, epochs) = run(11);
+        assert_eq!(epochs, vec![TTL + 1; 3]);
+
+        // a seed replays its schedule exactly, and another seed steps the workers differently to the same end.
+        assert_eq!(run(11), (schedule.clone(), epochs.clone()));
+        let (other, other_epochs) = run(12);
+        assert!(other != schedule);
+        assert_eq!(other_epochs, epochs);
+    }
+}
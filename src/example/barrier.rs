@@ -44,4 +44,6 @@ impl Scope<((), u64)> for BarrierScope {
 
         return false;
     }
+
+    fn schedule_on_activation(&self) -> bool { true }  // readied by push_external_progress
 }
@@ -50,4 +50,5 @@ impl<T:Timestamp> Scope<T> for ConcatScope<T> where <T as Columnar>::Stack: 'sta
     }
 
     fn notify_me(&self) -> bool { false }
+    fn schedule_on_activation(&self) -> bool { true }
 }
@@ -10,16 +10,22 @@ use communication::channels::Data;
 use communication::exchange::Exchange;
 use communication::observer::ObserverSessionExt;
 use example::stream::Stream;
-use example::unary::UnaryExt;
+use example::unary::{UnaryExt, Budget};
 
 use columnar::Columnar;
 
-pub trait DistinctExtensionTrait { fn distinct(&mut self) -> Self; }
+pub trait DistinctExtensionTrait {
+    fn distinct(&mut self) -> Self;
+    fn distinct_with_budget(&mut self, budget: Budget) -> Self;
+}
 
 impl<G: Graph, D: Data+Hash+Eq+Columnar> DistinctExtensionTrait for Stream<G, D> {
-    fn distinct(&mut self) -> Stream<G, D> {
+    fn distinct(&mut self) -> Stream<G, D> { self.distinct_with_budget(Default::default()) }
+
+    // as distinct, but taking at most budget of its input each time it is scheduled.
+    fn distinct_with_budget(&mut self, budget: Budget) -> Stream<G, D> {
         let mut elements: HashMap<_, HashSet<_, DefaultState<SipHasher>>> = HashMap::new();
-        self.unary(Exchange::new(|x| hash::<_,SipHasher>(&x)), format!("Distinct"), move |handle| {
+        self.unary_with_budget(Exchange::new(|x| hash::<_,SipHasher>(&x)), format!("Distinct"), budget, move |handle| {
             while let Some((time, data)) = handle.input.pull() {
                 let set = match elements.entry(time) {
                     Occupied(x) => { x.into_mut() },
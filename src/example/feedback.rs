@@ -70,6 +70,7 @@ impl<G: Graph, D: Data> Observer for FeedbackObserver<G, D> {
     }
     #[inline(always)] fn push(&mut self, data: &D) { if self.active { self.targets.push(data); } }
     #[inline(always)] fn shut(&mut self, time: &G::Timestamp) { if self.active { self.targets.shut(&self.summary.results_in(time)); } }
+    #[inline(always)] fn congested(&self) -> bool { self.targets.congested() }
 }
 
 
@@ -113,4 +114,5 @@ impl<T:Timestamp> Scope<T> for FeedbackScope<T> {
     }
 
     fn notify_me(&self) -> bool { false }
+    fn schedule_on_activation(&self) -> bool { true }
 }
@@ -60,6 +60,7 @@ impl<TOuter: Timestamp, TInner: Timestamp, TData: Data> Observer for IngressNub<
     #[inline(always)] fn push(&mut self, data: &TData) { self.targets.push(data); }
     #[inline(always)] fn open(&mut self, time: &TOuter) -> () { self.targets.open(&(time.clone(), Default::default())); }
     #[inline(always)] fn shut(&mut self, time: &TOuter) -> () { self.targets.shut(&(time.clone(), Default::default())); }
+    #[inline(always)] fn congested(&self) -> bool { self.targets.congested() }
 }
 
 
@@ -75,4 +76,5 @@ where TOuter: Timestamp, TInner: Timestamp, TData: Data {
     #[inline(always)] fn open(&mut self, time: &(TOuter, TInner)) { self.targets.open(&time.0); }
     #[inline(always)] fn push(&mut self, data: &TData) { self.targets.push(data); }
     #[inline(always)] fn shut(&mut self, time: &(TOuter, TInner)) { self.targets.shut(&time.0); }
+    #[inline(always)] fn congested(&self) -> bool { self.targets.congested() }
 }
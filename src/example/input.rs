@@ -3,7 +3,7 @@ use std::cell::RefCell;
 use std::default::Default;
 
 use progress::frontier::{MutableAntichain, Antichain};
-use progress::{Graph, Scope, Timestamp};
+use progress::{Graph, Scope, Timestamp, Activator};
 use progress::subgraph::Source::{ScopeOutput};
 use progress::count_map::CountMap;
 use communication::Communicator;
@@ -26,6 +26,7 @@ impl<G: Graph> InputExtensionTrait<G> for G {
             frontier: Rc::new(RefCell::new(MutableAntichain::new_bottom(Default::default()))),
             progress: Rc::new(RefCell::new(CountMap::new())),
             output:   ObserverHelper::new(output.clone(), produced.clone()),
+            activator: Rc::new(RefCell::new(None)),
         };
 
         // TODO : Ask borrow_ck why it has a hard time with this
@@ -37,6 +38,7 @@ impl<G: Graph> InputExtensionTrait<G> for G {
             progress: helper.progress.clone(),
             messages: produced.clone(),
             copies:   copies,
+            activator: helper.activator.clone(),
         });
 
         return (helper, Stream::new(ScopeOutput(index, 0), output, self.clone()));
@@ -48,6 +50,7 @@ pub struct InputScope<T:Timestamp> {
     progress:   Rc<RefCell<CountMap<T>>>,           // times closed since last asked
     messages:   Rc<RefCell<CountMap<T>>>,           // messages sent since last asked
     copies:     u64,
+    activator:  Rc<RefCell<Option<Activator>>>,     // shared with the InputHelper, to report what it sends
 }
 
 impl<T:Timestamp> Scope<T> for InputScope<T> {
@@ -73,27 +76,44 @@ impl<T:Timestamp> Scope<T> for InputScope<T> {
     }
 
     fn notify_me(&self) -> bool { false }
+    fn schedule_on_activation(&self) -> bool { true }
+    fn set_activator(&mut self, activator: Activator) { *self.activator.borrow_mut() = Some(activator); }
 }
 
 pub struct InputHelper<T: Timestamp, D: Data> {
     frontier:   Rc<RefCell<MutableAntichain<T>>>,   // times available for sending
     progress:   Rc<RefCell<CountMap<T>>>,           // times closed since last asked
     output:     ObserverHelper<OutputPort<T, D>>,
+    activator:  Rc<RefCell<Option<Activator>>>,     // schedules the InputScope to report progress
 }
 
 impl<T:Timestamp, D: Data> InputHelper<T, D> {
-    pub fn send_messages(&mut self, time: &T, data: Vec<D>) {
+    // sends data at time, unless the output is congested, in which case the data are handed back to be sent later,
+    // once the worker has stepped and its receivers have caught up.
+    pub fn send_messages(&mut self, time: &T, data: Vec<D>) -> Result<(), Vec<D>> {
+        if self.congested() { return Err(data); }
         self.output.open(time);
         for datum in data.into_iter() { self.output.push(&datum); }
         self.output.shut(time);
+        self.activate();
+        Ok(())
     }
 
+    // whether data sent now would wait on a slow receiver (see Observer::congested).
+    pub fn congested(&self) -> bool { self.output.congested() }
+
     pub fn advance(&self, start: &T, end: &T) {
         self.frontier.borrow_mut().update_weight(start, -1, &mut (*self.progress.borrow_mut()));
         self.frontier.borrow_mut().update_weight(end,  1, &mut (*self.progress.borrow_mut()));
+        self.activate();
     }
 
     pub fn close_at(&self, time: &T) {
         self.frontier.borrow_mut().update_weight(time, -1, &mut (*self.progress.borrow_mut()));
+        self.activate();
+    }
+
+    fn activate(&self) {
+        if let Some(ref activator) = *self.activator.borrow() { activator.activate(); }
     }
 }
@@ -11,21 +11,51 @@ use communication::exchange::ParallelizationContract;
 use example::stream::Stream;
 use progress::count_map::CountMap;
 use progress::notificator::Notificator;
-use progress::{Timestamp, Scope, Antichain};
+use progress::{Timestamp, Scope, Antichain, Activator};
 use communication::channels::{Data, OutputPort, ObserverHelper};
 use communication::Pullable;
 
+use time;
+
+// Limits the input an operator's logic may pull each time it is scheduled, so that an operator with much input does
+// not keep the others of its worker waiting. once a limit is reached, the input stops returning messages until the
+// operator is next scheduled, which it asks to be; at least one message is returned each time, whatever the limits.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Budget {
+    pub records:    Option<u64>,    // records to pull at most, or None for no limit
+    pub time_ms:    Option<u64>,    // milliseconds to keep pulling at most, or None for no limit
+}
+
+impl Budget {
+    pub fn records(mut self, records: u64) -> Budget { self.records = Some(records); self }
+    pub fn time_ms(mut self, time_ms: u64) -> Budget { self.time_ms = Some(time_ms); self }
+}
+
 pub struct PullableHelper<T:Timestamp, D:Data, P: Pullable<(T, Vec<D>)>> {
     receiver:   P,
     consumed:   CountMap<T>,
     phantom:    PhantomData<D>,
+
+    budget:     Budget,
+    pulled:     u64,                // records pulled in this invocation of the operator
+    started:    u64,                // time of this invocation, in ns, if budget has a time limit
+    yielded:    bool,               // whether this invocation stopped pulling for lack of budget
+    held:       bool,               // whether this invocation pulls just one message, for a congested output
 }
 
 impl<T:Timestamp, D:Data, P: Pullable<(T, Vec<D>)>> Pullable<(T, Vec<D>)> for PullableHelper<T, D, P> {
     fn pull(&mut self) -> Option<(T, Vec<D>)> {
+        // messages are taken from the receiver one at a time, so that input beyond the budget stays in the channel
+        // (holding its sender's credit) rather than here. whether any is left is not known without taking it, so
+        // an exhausted budget always yields, and the next invocation may find nothing to do.
+        if self.exhausted() {
+            self.yielded = true;
+            return None;
+        }
         if let Some((time, data)) = self.receiver.pull() {
             if data.len() > 0 {
                 self.consumed.update(&time, data.len() as i64);
+                self.pulled += data.len() as u64;
                 Some((time, data))
             }
             else { None }
@@ -35,9 +65,40 @@ impl<T:Timestamp, D:Data, P: Pullable<(T, Vec<D>)>> Pullable<(T, Vec<D>)> for Pu
 }
 
 impl<T:Timestamp, D:Data, P: Pullable<(T, Vec<D>)>> PullableHelper<T, D, P> {
+    pub fn new(receiver: P) -> PullableHelper<T, D, P> {
+        PullableHelper {
+            receiver:   receiver,
+            consumed:   CountMap::new(),
+            phantom:    PhantomData,
+            budget:     Default::default(),
+            pulled:     0,
+            started:    0,
+            yielded:    false,
+            held:       false,
+        }
+    }
+
     pub fn pull_progress(&mut self, consumed: &mut CountMap<T>) {
         while let Some((ref time, value)) = self.consumed.pop() { consumed.update(time, value); }
     }
+
+    pub fn set_budget(&mut self, budget: Budget) { self.budget = budget; }
+
+    // starts a new invocation of the operator, with its budget unspent. an operator whose output is congested pulls
+    // one message only, leaving the rest in the channel (and so with its sender) until its receivers catch up. it
+    // does not pull nothing, as the operator may itself be what its receivers wait on, e.g. around a loop.
+    fn begin(&mut self, held: bool) {
+        self.pulled = 0;
+        self.yielded = false;
+        self.held = held;
+        if self.budget.time_ms.is_some() { self.started = time::precise_time_ns(); }
+    }
+
+    fn exhausted(&self) -> bool {
+        self.pulled > 0 && (self.held ||
+                            self.budget.records.map(|records| self.pulled >= records).unwrap_or(false) ||
+                            self.budget.time_ms.map(|ms| time::precise_time_ns() - self.started >= ms * 1_000_000).unwrap_or(false))
+    }
 }
 
 
@@ -47,6 +108,11 @@ pub trait UnaryExt<G: Graph, D1: Data, D2: Data> {
             //  O: Observer<Time=G::Timestamp, Data=D1>+'static,
             //  P: Pullable<(G::Timestamp, Vec<D1>)>>(&mut self, sender: O, receiver: P,
             (&mut self, pact: P, name: String, logic: L) -> Stream<G, D2>;
+
+    // as unary, but with the input pulled by logic limited to budget in each invocation.
+    fn unary_with_budget<L: FnMut(&mut UnaryScopeHandle<G::Timestamp, D1, D2, P::Pullable>)+'static,
+                         P: ParallelizationContract<G::Timestamp, D1>>
+            (&mut self, pact: P, name: String, budget: Budget, logic: L) -> Stream<G, D2>;
 }
 
 impl<G: Graph, D1: Data, D2: Data> UnaryExt<G, D1, D2> for Stream<G, D1> {
@@ -55,9 +121,16 @@ impl<G: Graph, D1: Data, D2: Data> UnaryExt<G, D1, D2> for Stream<G, D1> {
             //  O: Observer<Time=G::Timestamp, Data=D1>+'static,
             //  P: Pullable<(G::Timestamp, Vec<D1>)>+'static>
              (&mut self, pact: P, name: String, logic: L) -> Stream<G, D2> {
-        let (sender, receiver) = pact.connect(&mut self.graph.communicator());
+        self.unary_with_budget(pact, name, Default::default(), logic)
+    }
+
+    fn unary_with_budget<L: FnMut(&mut UnaryScopeHandle<G::Timestamp, D1, D2, P::Pullable>)+'static,
+                         P: ParallelizationContract<G::Timestamp, D1>>
+             (&mut self, pact: P, name: String, budget: Budget, logic: L) -> Stream<G, D2> {
+        let (sender, receiver) = pact.connect(&mut self.graph.communicator(), &name[..]);
         let targets = OutputPort::<G::Timestamp,D2>::new();
-        let scope = UnaryScope::new(receiver, targets.clone(), name, logic);
+        let mut scope = UnaryScope::new(receiver, targets.clone(), name, logic);
+        scope.handle.input.set_budget(budget);
         let index = self.graph.add_scope(scope);
         self.connect_to(ScopeInput(index, 0), sender);
         self.clone_with(ScopeOutput(index, 0), targets)
@@ -68,6 +141,14 @@ pub struct UnaryScopeHandle<T: Timestamp, D1: Data, D2: Data, P: Pullable<(T, Ve
     pub input:          PullableHelper<T, D1, P>,
     pub output:         ObserverHelper<OutputPort<T, D2>>,
     pub notificator:    Notificator<T>,
+    activator:          Option<Activator>,
+}
+
+impl<T: Timestamp, D1: Data, D2: Data, P: Pullable<(T, Vec<D1>)>> UnaryScopeHandle<T, D1, D2, P> {
+    // schedules the operator in the next step even if no input arrives, for logic that leaves work for later.
+    pub fn activate(&self) {
+        if let Some(ref activator) = self.activator { activator.activate(); }
+    }
 }
 
 pub struct UnaryScope<T: Timestamp, D1: Data, D2: Data, P: Pullable<(T, Vec<D1>)>, L: FnMut(&mut UnaryScopeHandle<T, D1, D2, P>)> {
@@ -81,9 +162,10 @@ impl<T: Timestamp, D1: Data, D2: Data, P: Pullable<(T, Vec<D1>)>, L: FnMut(&mut
         UnaryScope {
             name: name,
             handle: UnaryScopeHandle {
-                input:       PullableHelper { receiver: receiver, consumed: CountMap::new(), phantom: PhantomData },
+                input:       PullableHelper::new(receiver),
                 output:      ObserverHelper::new(targets.clone(), Rc::new(RefCell::new(CountMap::new()))),
                 notificator: Default::default(),
+                activator:   None,
             },
             logic: logic,
         }
@@ -108,8 +190,14 @@ impl<T: Timestamp, D1: Data, D2: Data, P: Pullable<(T, Vec<D1>)>, L: FnMut(&mut
                                          consumed: &mut Vec<CountMap<T>>,
                                          produced: &mut Vec<CountMap<T>>) -> bool
     {
+        // input is held back while the output is congested, one message at a time so that a loop keeps draining.
+        let congested = self.handle.output.congested();
+        self.handle.input.begin(congested);
         (self.logic)(&mut self.handle);
 
+        // input left for lack of budget is pulled in the next step
+        if self.handle.input.yielded { self.handle.activate(); }
+
         // extract what we know about progress from the input and output adapters.
         self.handle.input.pull_progress(&mut consumed[0]);
         self.handle.output.pull_progress(&mut produced[0]);
@@ -120,4 +208,109 @@ impl<T: Timestamp, D1: Data, D2: Data, P: Pullable<(T, Vec<D1>)>, L: FnMut(&mut
 
     fn name(&self) -> String { format!("{}", self.name) }
     fn notify_me(&self) -> bool { true }
+    fn schedule_on_activation(&self) -> bool { true }
+    fn set_activator(&mut self, activator: Activator) { self.handle.activator = Some(activator); }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    use communication::Pullable;
+    use communication::exchange::Exchange;
+    use progress::count_map::CountMap;
+
+    use super::{Budget, PullableHelper, UnaryExt};
+
+    #[test]
+    fn budgets_limit_records_per_invocation() {
+        use std::collections::VecDeque;
+
+        let queue = Rc::new(RefCell::new(VecDeque::new()));
+        for round in (0..4u64) { queue.borrow_mut().push_back((0u64, vec![round; 3])); }
+        let mut input = PullableHelper::new(queue.clone());
+        input.set_budget(Budget::default().records(5));
+
+        // two messages of three records exceed the budget of five, and then the input yields, leaving the rest queued
+        input.begin(false);
+        assert!(input.pull().is_some());
+        assert!(input.pull().is_some());
+        assert!(input.pull().is_none());
+        assert!(input.yielded);
+        assert_eq!(queue.borrow().len(), 2);
+
+        // the next invocation picks up where the last left off, and yields again having spent its budget
+        input.begin(false);
+        assert_eq!(input.pull(), Some((0, vec![2; 3])));
+        assert_eq!(input.pull(), Some((0, vec![3; 3])));
+        assert!(input.pull().is_none());
+        assert!(input.yielded);
+
+        // which an invocation that finds the input empty does not
+        input.begin(false);
+        assert!(input.pull().is_none());
+        assert!(!input.yielded);
+
+        let mut consumed = CountMap::new();
+        input.pull_progress(&mut consumed);
+        assert_eq!(consumed.elements(), &vec![(0, 12)]);
+    }
+
+    #[test]
+    fn congested_loops_between_processes_complete() {
+        use std::cell::Cell;
+        use std::thread;
+        use std::sync::mpsc::channel;
+        use communication::Communicator;
+        use communication::observer::ObserverSessionExt;
+        use example::input::InputExtensionTrait;
+        use example::concat::ConcatExtensionTrait;
+        use example::feedback::FeedbackExtensionTrait;
+        use networking::networking::{initialize_transport, DEFAULT_CREDITS};
+        use networking::transport::PipeTransport;
+        use progress::subgraph::{new_graph, step};
+        use progress::subgraph::Summary::Local;
+
+        // each record crosses to the other process in every round, as a message of its own, so that both processes
+        // have far more for each other than their channels have credit for, and both outputs are congested at once.
+        const RECORDS: u64 = 8 * DEFAULT_CREDITS;
+        const ROUNDS: u64 = 10;
+
+        let (counts_s, counts_r) = channel();
+        let mut handles = Vec::new();
+        for transport in PipeTransport::new_vector(2).unwrap() {
+            let counts_s = counts_s.clone();
+            handles.push(thread::spawn(move || {
+                let communicator = initialize_transport(transport, 1, 0, false, false).unwrap().pop().unwrap();
+                let index = communicator.index();
+                let mut graph = new_graph(communicator);
+
+                let (mut input, mut stream) = graph.new_input::<u64>();
+                let (mut feedback, mut feedback_output) = stream.feedback(((), ROUNDS), Local(1));
+                let finished = Rc::new(Cell::new(0));
+                let counted = finished.clone();
+                let mut bounced = stream.concat(&mut feedback_output).unary(Exchange::new(|x: &u64| *x), format!("Bounce"), move |handle| {
+                    while let Some((time, data)) = handle.input.pull() {
+                        if time.1 == ROUNDS { counted.set(counted.get() + data.len() as u64); }
+                        for datum in data.into_iter() { handle.output.session(&time).push(&(datum + 1)); }
+                    }
+                });
+                feedback.connect_input(&mut bounced);
+
+                graph.0.borrow_mut().get_internal_summary();
+                graph.0.borrow_mut().set_external_summary(Vec::new(), &mut Vec::new());
+                graph.0.borrow_mut().push_external_progress(&mut Vec::new());
+
+                input.send_messages(&((), 0), (index * RECORDS..(index + 1) * RECORDS).collect()).ok().expect("input congested");
+                input.close_at(&((), 0));
+                while step(&graph).unwrap() { }
+                counts_s.send(finished.get()).unwrap();
+            }));
+        }
+        for handle in handles { handle.join().unwrap(); }
+
+        // every record went around the loop to its last round, on one process or the other
+        assert_eq!(counts_r.recv().unwrap() + counts_r.recv().unwrap(), 2 * RECORDS);
+    }
 }
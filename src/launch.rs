@@ -0,0 +1,105 @@
+use std::io::{self, BufRead, BufReader, Read, Write, Result};
+use std::process::{Command, Stdio};
+use std::net::TcpListener;
+use std::fs::{self, File};
+use std::thread;
+use std::env;
+use std::path::PathBuf;
+
+use libc;
+use time;
+
+use networking::PipeTransport;
+
+// runs `processes` copies of this executable on this machine as one computation, each with `arguments` and its
+// own --processes and --processid. output lines are prefixed with the index of the process that wrote them.
+// returns whether every process exited successfully.
+pub fn launch(processes: u64, arguments: Vec<String>, transport: &str) -> Result<bool> {
+    let executable = try!(env::current_exe());
+
+    // a directory private to this run, for the tcp hostfile or for where unix and shm processes meet, so that
+    // nothing is shared with another run. it is removed on return, with anything the processes left behind.
+    let run = try!(RunDirectory::create());
+    let tcp = transport == "tcp";
+
+    // for pipes, the pipes between every pair of processes, which the processes inherit. the launcher's copies of
+    // the descriptors are closed once all processes have started, so that only the processes using a pipe hold it.
+    let pipes = if transport == "pipe" { try!(PipeTransport::new_vector(processes)) } else { Vec::new() };
+    let descriptors = PipeTransport::to_argument(&pipes[..]);
+
+    // for tcp, a hostfile of free ports on localhost, found by binding to port zero. the listeners are held
+    // until all ports are chosen so that no port is chosen twice, though another program may yet take one.
+    let hostfile = run.path.join("hosts");
+    if tcp {
+        let mut listeners = Vec::new();
+        for _ in (0..processes) { listeners.push(try!(TcpListener::bind("127.0.0.1:0"))); }
+        let mut file = try!(File::create(&hostfile));
+        for listener in listeners.iter() {
+            try!(writeln!(file, "127.0.0.1:{}", try!(listener.local_addr()).port()));
+        }
+    }
+
+    let mut children = Vec::new();
+    let mut forwarders = Vec::new();
+    for index in (0..processes) {
+        let mut command = Command::new(&executable);
+        command.args(&arguments[..])
+               .arg("--processes").arg(&processes.to_string())
+               .arg("--processid").arg(&index.to_string())
+               .stdout(Stdio::piped())
+               .stderr(Stdio::piped());
+        if tcp                      { command.arg("--hostfile").arg(&hostfile); }
+        else if transport == "pipe" { command.arg("--pipes").arg(&descriptors); }
+        else                        { command.arg("--rendezvous").arg(&run.path); }
+
+        let mut child = try!(command.spawn());
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        forwarders.push(thread::spawn(move || forward(index, stdout, false)));
+        forwarders.push(thread::spawn(move || forward(index, stderr, true)));
+        children.push(child);
+    }
+    drop(pipes);
+
+    let mut success = true;
+    for (index, child) in children.iter_mut().enumerate() {
+        let status = try!(child.wait());
+        if !status.success() {
+            println!("[launch] process {} failed: {}", index, status);
+            success = false;
+        }
+    }
+    for forwarder in forwarders.into_iter() { forwarder.join().ok(); }
+
+    Ok(success)
+}
+
+// a fresh directory under the system's temporary directory, removed with its contents when dropped.
+struct RunDirectory {
+    path:   PathBuf,
+}
+
+impl RunDirectory {
+    fn create() -> Result<RunDirectory> {
+        let path = env::temp_dir().join(&format!("timely-{}-{}", unsafe { libc::getpid() }, time::precise_time_ns()));
+        try!(fs::create_dir(&path));    // fails rather than share a directory that already exists
+        Ok(RunDirectory { path: path })
+    }
+}
+
+impl Drop for RunDirectory {
+    fn drop(&mut self) { fs::remove_dir_all(&self.path).ok(); }
+}
+
+// copies lines from a child's stdout or stderr to ours, prefixed with its process index.
+fn forward<R: Read>(index: u64, reader: R, error: bool) {
+    for line in BufReader::new(reader).lines() {
+        match line {
+            Ok(line) => {
+                if error { writeln!(&mut io::stderr(), "[{}] {}", index, line).ok(); }
+                else     { println!("[{}] {}", index, line); }
+            },
+            Err(_) => { return; },
+        }
+    }
+}
@@ -8,6 +8,9 @@
 extern crate core;
 extern crate columnar;
 extern crate byteorder;
+extern crate libc;
+extern crate unix_socket;
+extern crate time;
 
 pub mod networking;
 pub mod progress;
@@ -11,6 +11,9 @@ extern crate core;
 extern crate test;
 extern crate columnar;
 extern crate byteorder;
+extern crate libc;
+extern crate unix_socket;
+extern crate time;
 
 extern crate docopt;
 use docopt::Docopt;
@@ -19,22 +22,27 @@ use test::Bencher;
 
 use columnar::Columnar;
 
-use progress::{Graph, Scope};
-use progress::subgraph::new_graph;
+use progress::{Graph, Scope, Subgraph, Timestamp};
+use progress::subgraph::{self, new_graph, new_graph_with_protocol};
+use progress::broadcast::ProgressProtocol;
 use progress::subgraph::Summary::Local;
 use progress::subgraph::Source::ScopeOutput;
 use progress::subgraph::Target::ScopeInput;
-use communication::{ThreadCommunicator, ProcessCommunicator, Communicator};
+use communication::{ThreadCommunicator, ProcessCommunicator, LoopbackCommunicator, BinaryCommunicator, Communicator};
 
 use communication::channels::Data;
-use std::hash::Hash;
+use communication::exchange::Exchange;
+use communication::{Pushable, Pullable, PushHandle, PullHandle, Signal};
+use std::hash::{hash, Hash, SipHasher};
 use core::fmt::Debug;
+use std::any::Any;
 
 use example::stream::Stream;
 use example::input::InputExtensionTrait;
 use example::concat::ConcatExtensionTrait;
 use example::feedback::FeedbackExtensionTrait;
 use example::distinct::DistinctExtensionTrait;
+use example::unary::UnaryExt;
 // use example::command::CommandExtensionTrait;
 
 use example::graph_builder::{EnterSubgraphExt, LeaveSubgraphExt};
@@ -44,23 +52,52 @@ use std::rc::{Rc, try_unwrap};
 use std::cell::RefCell;
 
 use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::process;
+use std::env;
+use std::net::TcpListener;
 
-use networking::initialize_networking;
+use networking::{initialize_transport, binary_fingerprint, Transport, TcpTransport, UnixTransport, ShmTransport, PipeTransport, NetworkConfig, Retry};
+
+use std::path::{Path, PathBuf};
+use std::default::Default;
+use networking::config::check_address;
+use networking::compress::{compress, decompress};
+use byteorder::{LittleEndian, WriteBytesExt};
 
 mod progress;
 mod example;
 mod networking;
 mod communication;
+mod launch;
 
 static USAGE: &'static str = "
 Usage: timely distinct [options] [<arguments>...]
        timely barrier [options] [<arguments>...]
        timely command [options] [<arguments>...]
+       timely mixed [options] [<arguments>...]
+       timely exchange [options] [<arguments>...]
+       timely launch (distinct | barrier | command | mixed | exchange) [options] [<arguments>...]
 
 Options:
     -w <arg>, --workers <arg>    number of workers per process [default: 1]
     -p <arg>, --processid <arg>  identity of this process      [default: 0]
     -n <arg>, --processes <arg>  number of processes involved  [default: 1]
+    --transport <arg>            tcp, or unix, shm or pipe for same-host processes [default: tcp]
+    --hostfile <file>            addresses of the processes, one per line (otherwise localhost:2101, ...)
+    --rendezvous <dir>           directory private to this run, where unix or shm processes meet
+    --pipes <fds>                inherited pipe descriptors, for pipe processes started by timely launch
+    --bind <addr>                address to listen on, if not the one in the hostfile
+    --deadline <ms>              time allowed to connect to all processes  [default: 60000]
+    --checksum                   checksum messages between processes
+    --compress                   compress large messages between processes
+    --loopback                   serialize messages even between workers of one process
+    --aggregate-progress         merge progress updates within each process before sending them to others
+
+timely launch starts --processes processes on this machine, on free ports or with
+a fresh --rendezvous, and reports whether they all succeeded; their output is
+prefixed with their index.
 ";
 
 fn main() {
@@ -73,6 +110,23 @@ fn main() {
     let processes: u64 = if let Ok(processes) = args.get_str("-n").parse() { processes }
                          else { panic!("invalid setting for --processes: {}", args.get_str("-n")) };
 
+    // spawn the processes of a local cluster, and exit with their combined status.
+    if args.get_bool("launch") {
+        let mode = ["distinct", "barrier", "command", "mixed", "exchange"].iter().find(|&mode| args.get_bool(mode)).unwrap();
+        let transport = args.get_str("--transport");
+        let mut arguments = vec![mode.to_string(),
+                                 "--workers".to_string(), workers.to_string(),
+                                 "--transport".to_string(), transport.to_string(),
+                                 "--deadline".to_string(), args.get_str("--deadline").to_string()];
+        if args.get_bool("--checksum") { arguments.push("--checksum".to_string()); }
+        if args.get_bool("--compress") { arguments.push("--compress".to_string()); }
+        if args.get_bool("--aggregate-progress") { arguments.push("--aggregate-progress".to_string()); }
+        arguments.extend(args.get_vec("<arguments>").into_iter().map(|argument| argument.to_string()));
+
+        let success = launch::launch(processes, arguments, transport).unwrap_or_else(|error| panic!("error launching processes: {}", error));
+        process::exit(if success { 0 } else { 1 });
+    }
+
     println!("Hello, world!");
     println!("Starting timely with");
     println!("\tworkers:\t{}", workers);
@@ -82,11 +136,69 @@ fn main() {
     // vector holding communicators to use; one per local worker.
     if processes > 1 {
         println!("Initializing BinaryCommunicator");
-        let addresses = (0..processes).map(|index| format!("localhost:{}", 2101 + index).to_string()).collect();
-        let communicators = initialize_networking(addresses, process_id, workers).ok().expect("error initializing networking");
+
+        // peers must run the same binary and the same dataflow.
+        let mode = ["distinct", "barrier", "command", "mixed", "exchange"].iter().find(|&mode| args.get_bool(mode)).unwrap();
+        let fingerprint = hash::<_, SipHasher>(&(binary_fingerprint().ok().expect("error fingerprinting binary"), mode));
+        let checksum = args.get_bool("--checksum");
+        let compress = args.get_bool("--compress");
+        let deadline: u64 = if let Ok(deadline) = args.get_str("--deadline").parse() { deadline }
+                            else { panic!("invalid setting for --deadline: {}", args.get_str("--deadline")) };
+
+        let communicators = match args.get_str("--transport") {
+            "tcp"   => {
+                let mut config = if args.get_str("--hostfile") != "" {
+                    NetworkConfig::from_hostfile(Path::new(args.get_str("--hostfile")))
+                                  .unwrap_or_else(|error| panic!("error reading --hostfile: {}", error))
+                }
+                else { NetworkConfig::new((0..processes).map(|index| format!("localhost:{}", 2101 + index).to_string()).collect()) };
+
+                if config.addresses.len() as u64 != processes {
+                    panic!("--hostfile lists {} addresses, but --processes is {}", config.addresses.len(), processes);
+                }
+                if args.get_str("--bind") != "" {
+                    let bind = check_address(args.get_str("--bind")).unwrap_or_else(|error| panic!("invalid setting for --bind: {}", error));
+                    config = config.bind(bind.to_string());
+                }
+                config = config.deadline(deadline);
+
+                initialize_transport(TcpTransport::from_config(config, process_id), workers, fingerprint, checksum, compress)
+            },
+            "unix"  => {
+                let rendezvous = rendezvous(&args);
+                let paths = (0..processes).map(|index| rendezvous.join(&format!("{}.sock", index)).to_str().unwrap().to_string()).collect();
+                let retry = Retry { deadline_ms: deadline, .. Default::default() };
+                initialize_transport(UnixTransport::new(paths, process_id).retry(retry), workers, fingerprint, checksum, compress)
+            },
+            "shm"   => {
+                let directory = rendezvous(&args).join("shm");
+                initialize_transport(ShmTransport::new(directory, processes, process_id).deadline(deadline), workers, fingerprint, checksum, compress)
+            },
+            "pipe"  => {
+                // pipes are created before the processes using them, and so only by timely launch
+                if args.get_str("--pipes") == "" { panic!("--transport pipe needs --pipes, which timely launch provides"); }
+                let transport = PipeTransport::from_argument(args.get_str("--pipes"), process_id)
+                                              .unwrap_or_else(|error| panic!("invalid setting for --pipes: {}", error));
+                initialize_transport(transport, workers, fingerprint, checksum, compress)
+            },
+            other   => panic!("invalid setting for --transport: {}", other),
+        }.unwrap_or_else(|error| panic!("error initializing networking: {}", error));
+        let aggregate = args.get_bool("--aggregate-progress");
+        let communicators: Vec<_> = communicators.into_iter().map(|communicator| communicator.aggregate_progress(aggregate)).collect();
+        if args.get_bool("distinct") { _distinct_multi(communicators); }
+        else if args.get_bool("barrier") { _barrier_multi(communicators); }
+        else if args.get_bool("command") { _command_multi(communicators); }
+        else if args.get_bool("mixed") { _mixed_multi(communicators); }
+        else if args.get_bool("exchange") { _exchange_multi(communicators); }
+    }
+    else if args.get_bool("--loopback") {
+        println!("Initializing LoopbackCommunicator");
+        let communicators = LoopbackCommunicator::new_vector(workers);
         if args.get_bool("distinct") { _distinct_multi(communicators); }
         else if args.get_bool("barrier") { _barrier_multi(communicators); }
         else if args.get_bool("command") { _command_multi(communicators); }
+        else if args.get_bool("mixed") { _mixed_multi(communicators); }
+        else if args.get_bool("exchange") { _exchange_multi(communicators); }
     }
     else if workers > 1 {
         println!("Initializing ProcessCommunicator");
@@ -94,6 +206,8 @@ fn main() {
         if args.get_bool("distinct") { _distinct_multi(communicators); }
         else if args.get_bool("barrier") { _barrier_multi(communicators); }
         else if args.get_bool("command") { _command_multi(communicators); }
+        else if args.get_bool("mixed") { _mixed_multi(communicators); }
+        else if args.get_bool("exchange") { _exchange_multi(communicators); }
     }
     else {
         println!("Initializing ThreadCommunicator");
@@ -101,11 +215,26 @@ fn main() {
         if args.get_bool("distinct") { _distinct_multi(communicators); }
         else if args.get_bool("barrier") { _barrier_multi(communicators); }
         else if args.get_bool("command") { _command_multi(communicators); }
+        else if args.get_bool("mixed") { _mixed_multi(communicators); }
+        else if args.get_bool("exchange") { _exchange_multi(communicators); }
     };
 }
 
+// the directory where same-host processes meet. it must be private to the run, or processes of different runs may
+// meet each other, or what an earlier run left behind; timely launch creates one for each run.
+fn rendezvous(args: &docopt::ArgvMap) -> PathBuf {
+    if args.get_str("--rendezvous") == "" {
+        panic!("--transport {} needs --rendezvous, a directory private to this run (timely launch makes one)", args.get_str("--transport"));
+    }
+    PathBuf::from(args.get_str("--rendezvous"))
+}
+
 #[bench]
 fn distinct_bench(bencher: &mut Bencher) { _distinct(ProcessCommunicator::new_vector(1).swap_remove(0), Some(bencher)); }
+// as distinct_bench, but with every channel boxed, as all were before channels became typed handles; the difference
+// is what static dispatch buys.
+#[bench]
+fn distinct_boxed_bench(bencher: &mut Bencher) { _distinct(BoxedCommunicator(ProcessCommunicator::new_vector(1).swap_remove(0)), Some(bencher)); }
 fn _distinct_multi<C: Communicator+Send>(communicators: Vec<C>) {
     let mut guards = Vec::new();
     for communicator in communicators.into_iter() {
@@ -135,6 +264,254 @@ fn _barrier_multi<C: Communicator+Send>(communicators: Vec<C>) {
     }
 }
 
+// the barrier_*_bench compare progress protocols among eight and among sixty-four workers of one process: the time
+// per run is the latency side, and the progress batches pushed (printed with --nocapture) the bandwidth side.
+#[bench]
+fn barrier_broadcast_bench(bencher: &mut Bencher) { bencher.iter(|| _barrier_protocol(8, ProgressProtocol::Broadcast, 1000)); }
+#[bench]
+fn barrier_centralized_bench(bencher: &mut Bencher) { bencher.iter(|| _barrier_protocol(8, ProgressProtocol::Centralized, 1000)); }
+#[bench]
+fn barrier_broadcast_64_bench(bencher: &mut Bencher) { bencher.iter(|| _barrier_protocol(64, ProgressProtocol::Broadcast, 100)); }
+#[bench]
+fn barrier_centralized_64_bench(bencher: &mut Bencher) { bencher.iter(|| _barrier_protocol(64, ProgressProtocol::Centralized, 100)); }
+
+// runs `epochs` of a barrier on `workers` threads, with the supplied progress protocol, and prints the batches pushed.
+fn _barrier_protocol(workers: u64, protocol: ProgressProtocol, epochs: u64) {
+    let pushed = Arc::new(AtomicUsize::new(0));
+    {
+        let mut guards = Vec::new();
+        for communicator in ProcessCommunicator::new_vector(workers).into_iter() {
+            let pushed = pushed.clone();
+            guards.push(thread::scoped(move || {
+                let mut graph = new_graph_with_protocol(CountingCommunicator(communicator, pushed), protocol);
+                let peers = graph.communicator().peers();
+                graph.add_scope(BarrierScope { epoch: 0, ready: true, degree: peers, ttl: epochs });
+                graph.connect(ScopeOutput(0, 0), ScopeInput(0, 0));
+
+                graph.0.borrow_mut().get_internal_summary();
+                graph.0.borrow_mut().set_external_summary(Vec::new(), &mut Vec::new());
+                graph.0.borrow_mut().push_external_progress(&mut Vec::new());
+                while step(&graph) { }
+            }));
+        }
+    }
+    println!("{:?}:\t{} progress batches pushed", protocol, pushed.load(Ordering::SeqCst));
+}
+
+#[bench]
+fn mixed_bench(bencher: &mut Bencher) { _mixed(ProcessCommunicator::new_vector(1).swap_remove(0), Some(bencher), 1000, 10000); }
+fn _mixed_multi<C: Communicator+Send>(communicators: Vec<C>) {
+    let mut guards = Vec::new();
+    for communicator in communicators.into_iter() {
+        guards.push(thread::scoped(move || _mixed(communicator, None, 1000, 10000)));
+    }
+}
+
+// mixed_tcp_bench and mixed_tcp_unprioritized_bench time a short mixed run of two processes over loopback tcp,
+// with progress written ahead of bulk data and without, so the difference is what prioritization buys.
+#[bench]
+fn mixed_tcp_bench(bencher: &mut Bencher) {
+    bencher.iter(|| _cluster(_loopback_transports(2), 1, false, |communicator| _mixed(communicator.prioritize(true), None, 20, 100)));
+}
+#[bench]
+fn mixed_tcp_unprioritized_bench(bencher: &mut Bencher) {
+    bencher.iter(|| _cluster(_loopback_transports(2), 1, false, |communicator| _mixed(communicator.prioritize(false), None, 20, 100)));
+}
+
+// a barrier (progress traffic only) running alongside an exchange of `rounds` large batches (bulk traffic).
+// reports how long the barrier takes to complete its epochs, which is dominated by progress latency.
+fn _mixed<C: Communicator>(communicator: C, bencher: Option<&mut Bencher>, rounds: u64, epochs: u64) {
+    let mut graph = new_graph(communicator);
+
+    let peers = graph.communicator().peers();
+    let index = graph.communicator().index();
+
+    // bulk traffic: batches of records exchanged between all workers, and then discarded.
+    let (mut input, mut stream) = graph.new_input::<u64>();
+    let _discard: Stream<_, u64> = stream.unary(Exchange::new(|x: &u64| *x), format!("Discard"), |handle| {
+        while let Some(_) = handle.input.pull() { }
+    });
+
+    // progress traffic: a barrier advancing through epochs.
+    let barrier = graph.add_scope(BarrierScope { epoch: 0, ready: true, degree: peers, ttl: epochs });
+    graph.connect(ScopeOutput(barrier, 0), ScopeInput(barrier, 0));
+
+    // start things up!
+    graph.0.borrow_mut().get_internal_summary();
+    graph.0.borrow_mut().set_external_summary(Vec::new(), &mut Vec::new());
+    graph.0.borrow_mut().push_external_progress(&mut Vec::new());
+
+    let batch: Vec<u64> = (0..(1 << 16)).collect();
+
+    match bencher {
+        Some(b) => b.iter(|| {
+            input.send_messages(&((), 0), batch.clone()).ok().expect("input congested");
+            graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new());
+        }),
+        None    => {
+            let start = time::precise_time_s();
+            let mut sent = 0;
+            loop {
+                // keep bulk data flowing, but only as fast as the network accepts it.
+                if sent < rounds && !input.congested() {
+                    input.send_messages(&((), 0), batch.clone()).ok().expect("input congested");
+                    sent += 1;
+                    if sent == rounds { input.close_at(&((), 0)); }
+                }
+
+                if !step(&graph) && sent == rounds {
+                    break;
+                }
+            }
+            println!("worker {}:\t{} barrier epochs under bulk load in {}s", index, epochs, time::precise_time_s() - start);
+        }
+    }
+}
+
+// batches like those _exchange sends, as the little-endian u64s they encode to.
+fn exchange_batch() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for record in (0..(1u64 << 16)) { bytes.write_u64::<LittleEndian>(record).unwrap(); }
+    bytes
+}
+
+// compress_bench and decompress_bench show the cpu side of the --compress trade-off as bytes/s, and print the
+// bandwidth side as a compression ratio (visible with --nocapture).
+#[bench]
+fn compress_bench(bencher: &mut Bencher) {
+    let batch = exchange_batch();
+    let mut compressed = Vec::new();
+    compress(&batch[..], &mut compressed);
+    println!("compressed {} bytes to {} ({}x)", batch.len(), compressed.len(), batch.len() as f64 / compressed.len() as f64);
+
+    bencher.bytes = batch.len() as u64;
+    bencher.iter(|| { compressed.clear(); compress(&batch[..], &mut compressed); });
+}
+
+#[bench]
+fn decompress_bench(bencher: &mut Bencher) {
+    let batch = exchange_batch();
+    let mut compressed = Vec::new();
+    let mut decompressed = Vec::new();
+    compress(&batch[..], &mut compressed);
+
+    bencher.bytes = batch.len() as u64;
+    bencher.iter(|| { decompress(&compressed[..], &mut decompressed, batch.len() as u64).unwrap(); });
+}
+
+#[bench]
+fn exchange_bench(bencher: &mut Bencher) { _exchange(ProcessCommunicator::new_vector(1).swap_remove(0), Some(bencher), 1000, 1 << 16); }
+fn _exchange_multi<C: Communicator+Send>(communicators: Vec<C>) {
+    let mut guards = Vec::new();
+    for communicator in communicators.into_iter() {
+        guards.push(thread::scoped(move || _exchange(communicator, None, 1000, 1 << 16)));
+    }
+}
+
+// exchange_tcp_bench and exchange_shm_bench compare the transports between two processes on one host; bytes/s
+// counts the records each process sends, whether to the other process or to itself.
+#[bench]
+fn exchange_tcp_bench(bencher: &mut Bencher) {
+    bencher.bytes = 2 * 20 * (1 << 16) * 8;
+    bencher.iter(|| _cluster(_loopback_transports(2), 1, false, |communicator| _exchange(communicator, None, 20, 1 << 16)));
+}
+#[bench]
+fn exchange_shm_bench(bencher: &mut Bencher) {
+    bencher.bytes = 2 * 20 * (1 << 16) * 8;
+    bencher.iter(|| _cluster(_shm_transports(2), 1, false, |communicator| _exchange(communicator, None, 20, 1 << 16)));
+}
+
+// exchange_tcp_raw_bench and exchange_tcp_compressed_bench show the end-to-end trade-off of --compress: bytes/s
+// of records exchanged (the cpu side, on loopback where bandwidth is cheap), and the bytes each process put on the
+// wire (the bandwidth side, printed with --nocapture).
+#[bench]
+fn exchange_tcp_raw_bench(bencher: &mut Bencher) {
+    bencher.bytes = 2 * 20 * (1 << 16) * 8;
+    bencher.iter(|| _cluster(_loopback_transports(2), 1, false, |communicator| _exchange_counted(communicator, 20)));
+}
+#[bench]
+fn exchange_tcp_compressed_bench(bencher: &mut Bencher) {
+    bencher.bytes = 2 * 20 * (1 << 16) * 8;
+    bencher.iter(|| _cluster(_loopback_transports(2), 1, true, |communicator| _exchange_counted(communicator, 20)));
+}
+
+// as _exchange, then prints the bytes this process sent to others, headers included.
+fn _exchange_counted(communicator: BinaryCommunicator, rounds: u64) {
+    let index = communicator.index();
+    let statistics = communicator.statistics.clone();
+    _exchange(communicator, None, rounds, 1 << 16);
+    let sent = statistics.iter().fold(0, |sum, traffic| {
+        sum + traffic.lock().ok().expect("mutex error?").sent.values().fold(0, |sum, channel| sum + channel.bytes)
+    });
+    println!("worker {}:	sent {} bytes", index, sent);
+}
+
+// the receive path over loopback tcp, for many small messages per read rather than exchange_tcp_bench's messages of
+// more than half a receive window each, which are read in place.
+#[bench]
+fn exchange_tcp_small_bench(bencher: &mut Bencher) {
+    bencher.bytes = 2 * 5000 * (1 << 6) * 8;
+    bencher.iter(|| _cluster(_loopback_transports(2), 1, false, |communicator| _exchange(communicator, None, 5000, 1 << 6)));
+}
+
+// exchanges `rounds` batches of `batch` records between all workers, reporting throughput. useful to compare transports.
+fn _exchange<C: Communicator>(communicator: C, bencher: Option<&mut Bencher>, rounds: u64, batch: u64) {
+    let mut graph = new_graph(communicator);
+
+    let index = graph.communicator().index();
+
+    let received = Rc::new(RefCell::new(0u64));
+    let counter = received.clone();
+
+    let (mut input, mut stream) = graph.new_input::<u64>();
+    let _count: Stream<_, u64> = stream.unary(Exchange::new(|x: &u64| *x), format!("Count"), move |handle| {
+        while let Some((_, data)) = handle.input.pull() { *counter.borrow_mut() += data.len() as u64; }
+    });
+
+    // start things up!
+    graph.0.borrow_mut().get_internal_summary();
+    graph.0.borrow_mut().set_external_summary(Vec::new(), &mut Vec::new());
+    graph.0.borrow_mut().push_external_progress(&mut Vec::new());
+
+    let batch: Vec<u64> = (0..batch).collect();
+
+    match bencher {
+        Some(b) => b.iter(|| {
+            input.send_messages(&((), 0), batch.clone()).ok().expect("input congested");
+            graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new());
+        }),
+        None    => {
+            let start = time::precise_time_s();
+            let mut sent = 0;
+            loop {
+                if sent < rounds && !input.congested() {
+                    input.send_messages(&((), 0), batch.clone()).ok().expect("input congested");
+                    sent += 1;
+                    if sent == rounds { input.close_at(&((), 0)); }
+                }
+
+                if !step(&graph) && sent == rounds {
+                    break;
+                }
+            }
+            let elapsed = time::precise_time_s() - start;
+            let received = *received.borrow();
+            println!("worker {}:\treceived {} records in {}s ({} records/s)", index, received, elapsed, received as f64 / elapsed);
+        }
+    }
+}
+
+// performs one round of progress, exiting the process if a peer process has failed.
+fn step<T: Timestamp, C: Communicator>(graph: &(Rc<RefCell<Subgraph<(), T>>>, Rc<RefCell<C>>)) -> bool {
+    match subgraph::step(graph) {
+        Ok(active)   => active,
+        Err(failure) => {
+            println!("worker {}: shutting down: {}", graph.1.borrow().index(), failure);
+            process::exit(1);
+        }
+    }
+}
+
 fn _create_subgraph<G: Graph, D: Data+Hash+Eq+Debug+Columnar>(graph: &mut G, source1: &mut Stream<G, D>, source2: &mut Stream<G, D>) -> (Stream<G, D>, Stream<G, D>) {
     // build up a subgraph using the concatenated inputs/feedbacks
     let subgraph = Rc::new(RefCell::new(graph.new_subgraph::<u64>()));
@@ -155,6 +532,68 @@ fn _create_subgraph<G: Graph, D: Data+Hash+Eq+Debug+Columnar>(graph: &mut G, sou
     return (sub_egress1, sub_egress2);
 }
 
+// a ProcessCommunicator whose channels are handed out boxed, so that each push and pull is dynamically dispatched.
+struct BoxedCommunicator(ProcessCommunicator);
+impl Communicator for BoxedCommunicator {
+    fn index(&self) -> u64 { self.0.index() }
+    fn peers(&self) -> u64 { self.0.peers() }
+    fn signal(&self) -> Option<Signal> { self.0.signal() }
+    fn new_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        let (pushers, puller) = self.0.new_channel(name);
+        (pushers.into_iter().map(|pusher| PushHandle::Boxed(Box::new(pusher) as Box<Pushable<T>>)).collect(),
+         PullHandle::Boxed(Box::new(puller) as Box<Pullable<T>>))
+    }
+}
+
+// a ProcessCommunicator that counts the messages pushed on its channels, as a measure of the bandwidth they need.
+struct CountingCommunicator(ProcessCommunicator, Arc<AtomicUsize>);
+impl Communicator for CountingCommunicator {
+    fn index(&self) -> u64 { self.0.index() }
+    fn peers(&self) -> u64 { self.0.peers() }
+    fn signal(&self) -> Option<Signal> { self.0.signal() }
+    fn new_channel<T:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<T>>, PullHandle<T>) {
+        let (pushers, puller) = self.0.new_channel(name);
+        let pushed = self.1.clone();
+        (pushers.into_iter().map(|pusher| PushHandle::Boxed(Box::new(Counted(pusher, pushed.clone())) as Box<Pushable<T>>)).collect(), puller)
+    }
+}
+
+struct Counted<T>(PushHandle<T>, Arc<AtomicUsize>);
+impl<T:Send+'static> Pushable<T> for Counted<T> {
+    fn push(&mut self, data: T) { self.1.fetch_add(1, Ordering::SeqCst); self.0.push(data); }
+}
+
+// runs logic on each worker of as many processes as there are transports, all within this process and connected by
+// BinaryCommunicators, so that benchmarks measure the networking path without a cluster. returns once all are done.
+fn _cluster<T, F>(transports: Vec<T>, workers: u64, compress: bool, logic: F) where T: Transport+Send, F: Fn(BinaryCommunicator)+Sync {
+    let logic = &logic;
+    let mut guards = Vec::new();
+    for transport in transports.into_iter() {
+        guards.push(thread::scoped(move || {
+            let communicators = initialize_transport(transport, workers, 0, false, compress)
+                                    .unwrap_or_else(|error| panic!("error initializing networking: {}", error));
+            let mut guards = Vec::new();
+            for communicator in communicators.into_iter() {
+                guards.push(thread::scoped(move || logic(communicator)));
+            }
+        }));
+    }
+}
+
+// shm transports for processes on this host, meeting in a fresh directory, which connecting removes.
+fn _shm_transports(processes: u64) -> Vec<ShmTransport> {
+    let directory = env::temp_dir().join(&format!("timely-bench-{}-{}", unsafe { libc::getpid() }, time::precise_time_ns()));
+    (0..processes).map(|index| ShmTransport::new(directory.clone(), processes, index).capacity(1 << 20)).collect()
+}
+
+// tcp transports for processes connected over loopback, on ports found free by binding to port zero.
+fn _loopback_transports(processes: u64) -> Vec<TcpTransport> {
+    let listeners: Vec<_> = (0..processes).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let addresses: Vec<String> = listeners.iter().map(|listener| format!("127.0.0.1:{}", listener.local_addr().unwrap().port())).collect();
+    drop(listeners);
+    (0..processes).map(|index| TcpTransport::new(addresses.clone(), index)).collect()
+}
+
 fn _distinct<C: Communicator>(communicator: C, bencher: Option<&mut Bencher>) {
     // no "base scopes" yet, so the root pretends to be a subscope of some parent with a () timestamp type.
     let mut graph = new_graph(communicator);
@@ -185,8 +624,8 @@ fn _distinct<C: Communicator>(communicator: C, bencher: Option<&mut Bencher>) {
     graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new());
 
     // move some data into the dataflow graph.
-    input1.send_messages(&((), 0), vec![1u64]);
-    input2.send_messages(&((), 0), vec![2u64]);
+    input1.send_messages(&((), 0), vec![1u64]).ok().expect("input congested");
+    input2.send_messages(&((), 0), vec![2u64]).ok().expect("input congested");
 
     // see what everyone thinks about that ...
     graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new());
@@ -199,7 +638,7 @@ fn _distinct<C: Communicator>(communicator: C, bencher: Option<&mut Bencher>) {
     // spin
     match bencher {
         Some(b) => b.iter(|| { graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new()); }),
-        None    => while graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new()) { }
+        None    => while step(&graph) { }
     }
 }
 
@@ -245,6 +684,6 @@ fn _barrier<C: Communicator>(communicator: C, bencher: Option<&mut Bencher>) {
     // spin
     match bencher {
         Some(b) => b.iter(|| { graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new()); }),
-        None    => while graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new()) { },
+        None    => while step(&graph) { },
     }
 }
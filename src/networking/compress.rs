@@ -0,0 +1,171 @@
+use std::io::{Result, Error, ErrorKind};
+use std::cmp::min;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+// A small LZ77 compressor in the style of LZ4, for payloads sent between processes.
+//
+// compressed data begins with the uncompressed length as a little-endian u64, followed by sequences of literals
+// and a match. each sequence starts with a token byte: the high four bits hold the number of literals and the low
+// four bits the match length less MIN_MATCH, where 15 means that more bytes follow, each added to the length,
+// up to and including the first byte less than 255. then come the literals, then the distance back to the start
+// of the match as a little-endian u16. the final sequence ends after its literals, and has no match.
+
+const MIN_MATCH:    usize = 4;
+const MAX_OFFSET:   usize = 65535;
+const HASH_BITS:    usize = 12;
+
+pub fn compress(input: &[u8], output: &mut Vec<u8>) {
+    output.write_u64::<LittleEndian>(input.len() as u64).unwrap();
+
+    let mut table = vec![0usize; 1 << HASH_BITS];   // one more than the last position with each hash; zero if none
+    let mut anchor = 0;                             // start of literals not yet written
+    let mut position = 0;
+
+    while position + MIN_MATCH <= input.len() {
+        let hash = hash(&input[position..]);
+        let candidate = table[hash];
+        table[hash] = position + 1;
+
+        if candidate > 0 && position - (candidate - 1) <= MAX_OFFSET
+                         && &input[candidate - 1 .. candidate - 1 + MIN_MATCH] == &input[position .. position + MIN_MATCH] {
+            // matches may overlap the bytes they produce, which the decompressor copies one at a time.
+            let start = candidate - 1;
+            let mut length = MIN_MATCH;
+            while position + length < input.len() && input[start + length] == input[position + length] { length += 1; }
+
+            write_sequence(output, &input[anchor..position], Some((position - start, length)));
+            position += length;
+            anchor = position;
+        }
+        else { position += 1; }
+    }
+
+    write_sequence(output, &input[anchor..], None);
+}
+
+// decompresses into output, replacing its contents; errors rather than panics on corrupt input.
+pub fn decompress(mut input: &[u8], output: &mut Vec<u8>, limit: u64) -> Result<()> {
+    let length = try!(input.read_u64::<LittleEndian>());
+    if length > limit { return corrupt(format!("decompressed length {} exceeds limit {}", length, limit)); }
+    let length = length as usize;
+
+    output.clear();
+    output.reserve(min(length, 4 * input.len()));
+
+    loop {
+        if input.len() == 0 { return corrupt(format!("missing final sequence")); }
+        let token = input[0];
+        input = &input[1..];
+
+        let literals = try!(read_length(&mut input, (token >> 4) as usize));
+        if literals > input.len() || output.len() + literals > length { return corrupt(format!("literals out of bounds")); }
+        output.push_all(&input[..literals]);
+        input = &input[literals..];
+
+        // all but the final sequence continue with a match
+        if input.len() == 0 { break; }
+
+        let offset = try!(input.read_u16::<LittleEndian>()) as usize;
+        let matched = try!(read_length(&mut input, (token & 15) as usize)) + MIN_MATCH;
+        if offset == 0 || offset > output.len() || output.len() + matched > length { return corrupt(format!("match out of bounds")); }
+        let start = output.len() - offset;
+        for index in (0..matched) {
+            let byte = output[start + index];
+            output.push(byte);
+        }
+    }
+
+    if output.len() != length { return corrupt(format!("decompressed {} bytes, expected {}", output.len(), length)); }
+    Ok(())
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let word = (bytes[0] as u32) | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24;
+    (word.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let code = matched.map(|(_, length)| length - MIN_MATCH).unwrap_or(0);
+    output.push((min(literals.len(), 15) << 4 | min(code, 15)) as u8);
+    if literals.len() >= 15 { write_length(output, literals.len() - 15); }
+    output.push_all(literals);
+    if let Some((offset, _)) = matched {
+        output.write_u16::<LittleEndian>(offset as u16).unwrap();
+        if code >= 15 { write_length(output, code - 15); }
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 { output.push(255); length -= 255; }
+    output.push(length as u8);
+}
+
+fn read_length(input: &mut &[u8], initial: usize) -> Result<usize> {
+    let mut length = initial;
+    if initial == 15 {
+        loop {
+            if input.len() == 0 { return corrupt(format!("truncated length")); }
+            let byte = input[0];
+            *input = &input[1..];
+            length += byte as usize;
+            if byte < 255 { break; }
+        }
+    }
+    Ok(length)
+}
+
+fn corrupt<T>(reason: String) -> Result<T> {
+    Err(Error::new(ErrorKind::Other, format!("corrupt compressed data: {}", reason)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress};
+    use communication::simulation::XorShift;
+
+    #[test]
+    fn compression_roundtrips() {
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        let mut random = || rng.next();
+
+        let mut inputs: Vec<Vec<u8>> = vec![vec![], vec![7], vec![0; 100000], (0..100000).map(|x| (x % 251) as u8).collect()];
+        inputs.push((0..5000).map(|_| random() as u8).collect());                       // incompressible
+        inputs.push((0..5000).map(|_| (random() % 4) as u8).collect());                 // short matches
+        inputs.push((0..(1 << 12)).flat_map(|x: u64| (0..8).map(move |i| (x >> (8 * i)) as u8)).collect());    // encoded u64s
+
+        for input in inputs.iter() {
+            let mut compressed = Vec::new();
+            let mut decompressed = Vec::new();
+            compress(&input[..], &mut compressed);
+            decompress(&compressed[..], &mut decompressed, input.len() as u64).unwrap();
+            assert_eq!(&decompressed, input);
+        }
+
+        let mut compressed = Vec::new();
+        compress(&[0; 100000][..], &mut compressed);
+        assert!(compressed.len() < 1000);
+    }
+
+    #[test]
+    fn decompression_rejects_corrupt_input() {
+        let input: Vec<u8> = (0..10000).map(|x| (x % 97) as u8).collect();
+        let mut compressed = Vec::new();
+        let mut output = Vec::new();
+        compress(&input[..], &mut compressed);
+
+        assert!(decompress(&compressed[..], &mut output, 100).is_err());    // over the limit
+        for cut in (0..compressed.len()) {
+            assert!(decompress(&compressed[..cut], &mut output, 1 << 20).is_err());
+        }
+
+        let mut rng = XorShift::new(0x9E3779B97F4A7C15);
+        for _ in (0..1000) {
+            let mut mutated = compressed.clone();
+            let random = rng.next();
+            let position = (random % mutated.len() as u64) as usize;
+            mutated[position] ^= (random >> 32) as u8 | 1;
+            let _ = decompress(&mutated[..], &mut output, 1 << 20);     // must not panic
+        }
+    }
+}
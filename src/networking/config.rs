@@ -0,0 +1,114 @@
+use std::io::{Read, Result, Error, ErrorKind};
+use std::fs::File;
+use std::path::Path;
+use std::default::Default;
+
+// how connection attempts are retried: exponentially growing delays, up to an overall deadline.
+#[derive(Copy, Clone, Debug)]
+pub struct Retry {
+    pub initial_ms:     u32,    // delay after the first failed attempt
+    pub maximum_ms:     u32,    // cap on the delay between attempts
+    pub deadline_ms:    u64,    // time allowed to establish all connections, after which missing peers are reported
+}
+
+impl Default for Retry {
+    fn default() -> Retry { Retry { initial_ms: 100, maximum_ms: 5000, deadline_ms: 60000 } }
+}
+
+// where the processes of a computation are, and how to reach them.
+//
+// addresses are those each process advertises to its peers, as "host:port", with IPv6 literals in brackets
+// (e.g. "[::1]:2101"). a process may listen on a different address than it advertises, for example a wildcard
+// address like "0.0.0.0:2101" or "[::]:2101", or an interface address behind a NAT.
+#[derive(Clone, Debug)]
+pub struct NetworkConfig {
+    pub addresses:  Vec<String>,        // advertised address of each process, indexed by process
+    pub bind:       Option<String>,     // address this process listens on, if not its advertised address
+    pub retry:      Retry,
+}
+
+impl NetworkConfig {
+    pub fn new(addresses: Vec<String>) -> NetworkConfig {
+        NetworkConfig { addresses: addresses, bind: None, retry: Default::default() }
+    }
+
+    // reads one address per line; blank lines and lines starting with '#' are ignored.
+    pub fn from_hostfile(path: &Path) -> Result<NetworkConfig> {
+        let mut contents = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut contents));
+        Ok(NetworkConfig::new(try!(parse_hostfile(&contents[..]))))
+    }
+
+    pub fn bind(mut self, address: String) -> NetworkConfig { self.bind = Some(address); self }
+    pub fn backoff(mut self, initial_ms: u32, maximum_ms: u32) -> NetworkConfig {
+        self.retry.initial_ms = initial_ms;
+        self.retry.maximum_ms = maximum_ms;
+        self
+    }
+    pub fn deadline(mut self, deadline_ms: u64) -> NetworkConfig { self.retry.deadline_ms = deadline_ms; self }
+
+    // the address process `index` listens on.
+    pub fn bind_address(&self, index: u64) -> String {
+        self.bind.clone().unwrap_or_else(|| self.addresses[index as usize].clone())
+    }
+}
+
+pub fn parse_hostfile(contents: &str) -> Result<Vec<String>> {
+    let mut addresses = Vec::new();
+    for line in contents.lines().map(|line| line.trim()) {
+        if line.len() > 0 && !line.starts_with("#") {
+            addresses.push(try!(check_address(line)).to_string());
+        }
+    }
+    Ok(addresses)
+}
+
+// checks that an address has the form "host:port" or "[ipv6]:port", without resolving it.
+pub fn check_address(address: &str) -> Result<&str> {
+    let invalid = |reason: &str| Err(Error::new(ErrorKind::Other, format!("invalid address {:?}: {}", address, reason)));
+
+    let (host, port) = if address.starts_with("[") {
+        match address.find("]:") {
+            Some(end) => (&address[1..end], &address[end + 2..]),
+            None      => return invalid("expected [ipv6]:port"),
+        }
+    }
+    else {
+        match address.rfind(':') {
+            Some(split) => (&address[..split], &address[split + 1..]),
+            None        => return invalid("expected host:port"),
+        }
+    };
+
+    if host.len() == 0 { return invalid("missing host"); }
+    if !address.starts_with("[") && host.contains(":") { return invalid("IPv6 addresses must be bracketed, as in [::1]:2101"); }
+    if port.parse::<u16>().is_err() { return invalid("port must be a number from 0 to 65535"); }
+
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_hostfile, check_address};
+
+    #[test]
+    fn hostfiles_skip_comments_and_blank_lines() {
+        let contents = "# cluster\nnode0:2101\n\n  node1:2101  \n[::1]:2102\n# end\n";
+        assert_eq!(parse_hostfile(contents).unwrap(), vec!["node0:2101".to_string(), "node1:2101".to_string(), "[::1]:2102".to_string()]);
+    }
+
+    #[test]
+    fn addresses_are_checked() {
+        assert!(check_address("localhost:2101").is_ok());
+        assert!(check_address("10.0.0.1:0").is_ok());
+        assert!(check_address("[fe80::1]:2101").is_ok());
+
+        assert!(check_address("localhost").is_err());
+        assert!(check_address(":2101").is_err());
+        assert!(check_address("localhost:http").is_err());
+        assert!(check_address("localhost:65536").is_err());
+        assert!(check_address("::1:2101").is_err());
+        assert!(check_address("[::1]2101").is_err());
+        assert!(parse_hostfile("node0:2101\nnode1\n").is_err());
+    }
+}
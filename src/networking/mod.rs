@@ -1,3 +1,10 @@
-pub use networking::networking::initialize_networking;
+pub use networking::networking::{initialize_networking, initialize_transport, binary_fingerprint, Failure, PeerFailure, DataflowMismatch, NetworkStatistics, ChannelStatistics};
+pub use networking::transport::{Transport, TcpTransport, UnixTransport, PipeTransport};
+pub use networking::shm::ShmTransport;
+pub use networking::config::{NetworkConfig, Retry};
 
 pub mod networking;
+pub mod transport;
+pub mod shm;
+pub mod config;
+pub mod compress;
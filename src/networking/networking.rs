@@ -1,165 +1,698 @@
-// use std::old_io::{TcpListener, TcpStream};
-// use std::old_io::{Acceptor, Listener, IoResult, MemReader};
-use std::thread::sleep_ms;
-use std::io::{Read, Write, Result};
+use std::io::{Read, Write, Result, Error, ErrorKind};
+use std::fs::File;
+use std::env;
+use std::hash::{hash, SipHasher};
 
-use std::net::{TcpListener, TcpStream};
-use std::mem::size_of;
+use std::iter::repeat;
 
 use std::sync::mpsc::{Sender, Receiver, channel};
 
-use std::thread;
-use std::sync::{Arc, Future};
+use std::thread::{self, sleep_ms, JoinHandle};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
+use std::collections::{HashMap, VecDeque};
 use std::mem;
+use std::cmp::{min, max};
+use std::fmt::{self, Display, Formatter};
+use std::error;
+
+use time;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
-use communication::{Pushable, BinaryCommunicator, ProcessCommunicator};
+use communication::{Communicator, Pushable, BinaryCommunicator, ProcessCommunicator, Signal};
+use communication::ChannelTag;
+use networking::transport::{Transport, TcpTransport};
+use networking::compress::compress;
 
 // TODO : Much of this only relates to BinaryWriter/BinaryReader based communication, not networking.
 // TODO : Could be moved somewhere less networking-specific.
 
-#[derive(Copy, Clone)]
+pub const FLAG_GRANT:   u64 = 1 << 0;   // payload is a u64 count of messages the receiver has consumed
+pub const FLAG_CREDIT:  u64 = 1 << 1;   // a received grant, handed from a BinaryReceiver to its BinarySender
+pub const FLAG_PRIORITY:u64 = 1 << 2;   // control traffic (e.g. progress) written ahead of bulk data
+pub const FLAG_CHECKSUM:u64 = 1 << 3;   // payload is followed by a u64 checksum of its bytes
+pub const FLAG_HEARTBEAT:u64 = 1 << 4;  // empty message showing that the sending process is alive
+pub const FLAG_GOODBYE:  u64 = 1 << 5;  // the sending process is done, and will send nothing more but an acknowledgement
+pub const FLAG_GOODBYE_ACK:u64 = 1 << 6;// acknowledges a goodbye; the last message on a connection
+pub const FLAG_COMPRESSED:u64 = 1 << 7; // payload is compressed (see networking::compress)
+pub const FLAG_ANNOUNCE:u64 = 1 << 8;   // payload is the ChannelTag of a newly allocated channel, sent ahead of its messages
+pub const FLAG_LAYOUT: u64 = 1 << 9;    // payload is the ChannelTags of every channel a worker allocated, once it has built its dataflow
+
+// messages for the networking threads themselves, rather than for workers
+pub const FLAG_CONTROL: u64 = FLAG_GRANT | FLAG_HEARTBEAT | FLAG_GOODBYE | FLAG_GOODBYE_ACK | FLAG_ANNOUNCE | FLAG_LAYOUT;
+
+pub const DEFAULT_CREDITS:  u64 = 64;   // messages a channel may have in flight before the sender holds back
+pub const GRANT_BATCH:      u64 = 16;   // consumed messages a receiver accumulates before granting credit
+
+pub const COMPRESS_THRESHOLD: usize = 1 << 12;  // payloads smaller than this are not worth compressing
+
+pub const HEARTBEAT_INTERVAL_MS:    u32 = 1000;     // how often each connection is sent a heartbeat
+pub const HEARTBEAT_TIMEOUT_MS:     u64 = 10000;    // silence after which a peer process is presumed failed
+
+// the first failure of a peer process observed by the networking threads.
+#[derive(Clone, Debug)]
+pub struct PeerFailure {
+    pub process:    u64,        // index of the failed peer process
+    pub reason:     String,     // what was observed
+}
+
+impl Display for PeerFailure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "process {} failed: {}", self.process, self.reason)
+    }
+}
+
+impl error::Error for PeerFailure {
+    fn description(&self) -> &str { "peer process failed" }
+}
+
+// a channel that two workers allocated for different things, or that one allocated and the other did not. the
+// workers built different dataflows, which is no fault of either process and which waiting will not resolve.
+#[derive(Clone, Debug)]
+pub struct DataflowMismatch {
+    pub graph:      u64,
+    pub channel:    u64,
+    pub workers:    (u64, u64),                             // the two workers that disagree
+    pub tags:       (Option<ChannelTag>, Option<ChannelTag>),   // what each allocated the channel for, if anything
+}
+
+impl Display for DataflowMismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let describe = |tag: &Option<ChannelTag>| tag.as_ref().map(|tag| format!("{}", tag)).unwrap_or("nothing".to_string());
+        write!(f, "dataflow mismatch: channel {} of graph {} allocated by worker {} for {}, but by worker {} for {}; workers must build the same dataflow",
+               self.channel, self.graph, self.workers.0, describe(&self.tags.0), self.workers.1, describe(&self.tags.1))
+    }
+}
+
+impl error::Error for DataflowMismatch {
+    fn description(&self) -> &str { "workers built different dataflows" }
+}
+
+// why a worker cannot complete its dataflow, reported to workers by Communicator::failure().
+#[derive(Clone, Debug)]
+pub enum Failure {
+    Peer(PeerFailure),              // a peer process failed, or the connection to it did
+    Mismatch(DataflowMismatch),     // the workers built different dataflows
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Failure::Peer(ref failure)      => write!(f, "{}", failure),
+            Failure::Mismatch(ref mismatch) => write!(f, "{}", mismatch),
+        }
+    }
+}
+
+// counts of the messages on one (graph, channel, source, target) channel that have crossed a connection.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelStatistics {
+    pub messages:   u64,    // data messages, excluding control traffic
+    pub bytes:      u64,    // bytes on the wire, including headers and any checksums
+}
+
+impl ChannelStatistics {
+    fn record(&mut self, header: &MessageHeader) {
+        self.messages += 1;
+        self.bytes += HEADER_SIZE as u64 + header.length;
+    }
+}
+
+// traffic on the connections of a process, keyed by (graph, channel, source, target).
+#[derive(Clone, Debug, Default)]
+pub struct NetworkStatistics {
+    pub sent:       HashMap<(u64, u64, u64, u64), ChannelStatistics>,
+    pub received:   HashMap<(u64, u64, u64, u64), ChannelStatistics>,
+}
+
+impl NetworkStatistics {
+    fn record_sent(&mut self, header: &MessageHeader) {
+        self.sent.entry((header.graph, header.channel, header.source, header.target)).or_insert(Default::default()).record(header);
+    }
+    fn record_received(&mut self, header: &MessageHeader) {
+        self.received.entry((header.graph, header.channel, header.source, header.target)).or_insert(Default::default()).record(header);
+    }
+
+    // adds the counts of other to our own.
+    pub fn merge(&mut self, other: &NetworkStatistics) {
+        for (key, stats) in other.sent.iter() {
+            let entry = self.sent.entry(*key).or_insert(Default::default());
+            entry.messages += stats.messages;
+            entry.bytes += stats.bytes;
+        }
+        for (key, stats) in other.received.iter() {
+            let entry = self.received.entry(*key).or_insert(Default::default());
+            entry.messages += stats.messages;
+            entry.bytes += stats.bytes;
+        }
+    }
+}
+
+// records a failure, unless one has already been recorded; later failures are usually consequences of the first.
+pub fn record_failure(failure: &Mutex<Option<Failure>>, process: u64, reason: String) {
+    record(failure, Failure::Peer(PeerFailure { process: process, reason: reason }));
+}
+
+// records a mismatch, unless a failure has already been recorded.
+pub fn record_mismatch(failure: &Mutex<Option<Failure>>, mismatch: DataflowMismatch) {
+    record(failure, Failure::Mismatch(mismatch));
+}
+
+fn record(failure: &Mutex<Option<Failure>>, observed: Failure) {
+    let mut failure = failure.lock().ok().expect("mutex error?");
+    if failure.is_none() {
+        println!("networking:\t{}", observed);
+        *failure = Some(observed);
+    }
+}
+
+// milliseconds on a monotonic clock, for tracking when connections were last heard from.
+fn now_ms() -> usize { (time::precise_time_ns() / 1_000_000) as usize }
+
+// what the heartbeat thread for a connection learns from its BinaryReceiver.
+struct Liveness {
+    heard:      AtomicUsize,    // now_ms() when bytes last arrived
+    farewell:   AtomicBool,     // the peer has said goodbye, and its silence is expected
+}
+
+impl Liveness {
+    fn new() -> Liveness { Liveness { heard: AtomicUsize::new(now_ms()), farewell: AtomicBool::new(false) } }
+}
+
+// messages that arrived for channels their local workers have yet to allocate, keyed by (target, graph, channel).
+// shared by the BinaryReceivers of a process and its workers, either of which delivers them once the channel is
+// registered, so that neither waits on the other. credit bounds how many a channel can accumulate.
+pub type Unclaimed = Arc<Mutex<HashMap<(u64, u64, u64), Vec<(MessageHeader, Vec<u8>)>>>>;
+
+// the channels the first worker to report for each graph allocated, in order, as (worker, tags). shared by the workers
+// and BinaryReceivers of a process, so that whichever learns of another worker's layout compares it with the first.
+// announcements check each channel as it is allocated, but not channels that one worker allocated and another never did.
+pub type Layouts = Arc<Mutex<HashMap<u64, (u64, Vec<ChannelTag>)>>>;
+
+// records the channels `worker` allocated for `graph`, or returns how they differ from the first layout reported.
+pub fn compare_layout(layouts: &Layouts, graph: u64, worker: u64, tags: Vec<ChannelTag>) -> ::std::result::Result<(), DataflowMismatch> {
+    let mut layouts = layouts.lock().ok().expect("mutex error?");
+    if let Some(&(first, ref expected)) = layouts.get(&graph) {
+        for index in (0..max(expected.len(), tags.len())) {
+            if expected.get(index) != tags.get(index) {
+                return Err(DataflowMismatch {
+                    graph:      graph,
+                    channel:    index as u64,
+                    workers:    (first, worker),
+                    tags:       (expected.get(index).map(|tag| tag.clone()), tags.get(index).map(|tag| tag.clone())),
+                });
+            }
+        }
+        return Ok(());
+    }
+    layouts.insert(graph, (worker, tags));
+    Ok(())
+}
+
+// the networking threads of a process, shut down in an orderly fashion when the last BinaryCommunicator is dropped.
+//
+// each connection's BinarySender says goodbye once its outstanding messages are written, and acknowledges the
+// goodbye of its peer; each BinaryReceiver finishes once it has both the peer's goodbye and its acknowledgement of
+// ours. so, dropping the NetworkThreads waits until every peer process is also done, after which the threads exit
+// and are joined, and the connections are closed.
+pub struct NetworkThreads {
+    goodbyes:   Mutex<Vec<(MessageHeader, Sender<(MessageHeader, Vec<u8>)>)>>,  // for each BinarySender
+    threads:    Mutex<Vec<JoinHandle<()>>>,
+    failure:    Arc<Mutex<Option<Failure>>>,
+}
+
+impl Drop for NetworkThreads {
+    fn drop(&mut self) {
+        // a failed peer may never say goodbye, and a worker is likely unwinding because of it; don't wait.
+        if self.failure.lock().ok().expect("mutex error?").is_some() { return; }
+
+        for (header, sender) in self.goodbyes.lock().ok().expect("mutex error?").drain() {
+            sender.send((header, Vec::new())).ok();
+        }
+        for thread in self.threads.lock().ok().expect("mutex error?").drain() {
+            thread.join().ok();
+        }
+
+        if let Some(ref failure) = *self.failure.lock().ok().expect("mutex error?") {
+            println!("networking:\tshut down after failure: {}", failure);
+        }
+        else { println!("networking:\tshut down cleanly"); }
+    }
+}
+
+pub const PROTOCOL_MAGIC:   u64 = 0x74696d656c79;   // "timely"
+pub const PROTOCOL_VERSION: u64 = 4;                // bump when the wire format changes
+
+// exchanged on each new connection, before any messages, to confirm that both ends run the same computation.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Handshake {
+    pub magic:          u64,    // PROTOCOL_MAGIC, to recognize garbage
+    pub version:        u64,    // PROTOCOL_VERSION
+    pub process:        u64,    // index of the sending process
+    pub processes:      u64,    // number of processes
+    pub workers:        u64,    // workers per process
+    pub fingerprint:    u64,    // identifies the binary and dataflow, as supplied by the application
+    pub compress:       u64,    // 1 if the process would like to compress; a connection compresses if both ends would
+}
+
+impl Handshake {
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        try!(writer.write_u64::<LittleEndian>(self.magic));
+        try!(writer.write_u64::<LittleEndian>(self.version));
+        try!(writer.write_u64::<LittleEndian>(self.process));
+        try!(writer.write_u64::<LittleEndian>(self.processes));
+        try!(writer.write_u64::<LittleEndian>(self.workers));
+        try!(writer.write_u64::<LittleEndian>(self.fingerprint));
+        try!(writer.write_u64::<LittleEndian>(self.compress));
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> Result<Handshake> {
+        Ok(Handshake {
+            magic:          try!(reader.read_u64::<LittleEndian>()),
+            version:        try!(reader.read_u64::<LittleEndian>()),
+            process:        try!(reader.read_u64::<LittleEndian>()),
+            processes:      try!(reader.read_u64::<LittleEndian>()),
+            workers:        try!(reader.read_u64::<LittleEndian>()),
+            fingerprint:    try!(reader.read_u64::<LittleEndian>()),
+            compress:       try!(reader.read_u64::<LittleEndian>()),
+        })
+    }
+
+    // checks a handshake received on the connection to process `expected` against our own.
+    fn verify(&self, theirs: &Handshake, expected: u64) -> Result<()> {
+        let mismatch = |what: &str, ours: u64, other: u64| {
+            Err(Error::new(ErrorKind::Other, format!("process {}: handshake with process {} failed: {} mismatch (ours: {}, theirs: {})",
+                                                      self.process, expected, what, ours, other)))
+        };
+
+        if theirs.magic != self.magic { return mismatch("protocol magic", self.magic, theirs.magic); }
+        if theirs.version != self.version { return mismatch("protocol version", self.version, theirs.version); }
+        if theirs.process != expected { return mismatch("process index", expected, theirs.process); }
+        if theirs.processes != self.processes { return mismatch("process count", self.processes, theirs.processes); }
+        if theirs.workers != self.workers { return mismatch("workers per process", self.workers, theirs.workers); }
+        if theirs.fingerprint != self.fingerprint { return mismatch("dataflow fingerprint", self.fingerprint, theirs.fingerprint); }
+        Ok(())
+    }
+}
+
+// a fingerprint of the running executable, for applications to fold into their handshake fingerprint.
+pub fn binary_fingerprint() -> Result<u64> {
+    let mut bytes = Vec::new();
+    try!(try!(File::open(&try!(env::current_exe()))).read_to_end(&mut bytes));
+    Ok(hash::<_, SipHasher>(&bytes))
+}
+
+#[derive(Copy, Clone, Debug)]
 pub struct MessageHeader {
     pub graph:      u64,   // graph identifier
     pub channel:    u64,   // index of channel
     pub source:     u64,   // index of worker sending message
     pub target:     u64,   // index of worker receiving message
     pub length:     u64,   // number of bytes in message
+    pub flags:      u64,   // FLAG_* bits describing the message
 }
 
+pub const HEADER_SIZE:  usize = 48;         // six little-endian u64 fields
+pub const MAX_LENGTH:   u64 = 1 << 32;      // longer messages are taken to indicate a corrupt stream
+pub const MAX_INDEX:    u64 = 1 << 20;      // as are graph or channel identifiers beyond this
+
 impl MessageHeader {
-    // returns a header when there is enough supporting data
-    fn try_read(bytes: &mut &[u8]) -> Option<MessageHeader> {
-        if bytes.len() > size_of::<MessageHeader>() {
-            let headers: &[MessageHeader] = unsafe { mem::transmute((*bytes).clone()) };
-            let header = headers[0];
-            if bytes.len() >= size_of::<MessageHeader>() + header.length as usize {
-                *bytes = &(*bytes)[size_of::<MessageHeader>()..];
-                return Some(header);
+    // returns a header when there are enough bytes for one, and an error if the header is implausible.
+    pub fn peek(bytes: &[u8]) -> Result<Option<MessageHeader>> {
+        if bytes.len() >= HEADER_SIZE {
+            let mut fields = &bytes[..HEADER_SIZE];
+            let header = MessageHeader {
+                graph:      try!(fields.read_u64::<LittleEndian>()),
+                channel:    try!(fields.read_u64::<LittleEndian>()),
+                source:     try!(fields.read_u64::<LittleEndian>()),
+                target:     try!(fields.read_u64::<LittleEndian>()),
+                length:     try!(fields.read_u64::<LittleEndian>()),
+                flags:      try!(fields.read_u64::<LittleEndian>()),
+            };
+
+            if header.length > MAX_LENGTH || header.graph >= MAX_INDEX || header.channel >= MAX_INDEX {
+                return Err(Error::new(ErrorKind::Other, format!("implausible message header: {:?}", header)));
             }
-            else {
-                println!("bytes.len() = {}, needed = {}", bytes.len(), size_of::<MessageHeader>() + header.length as usize);
+            if header.flags & FLAG_CHECKSUM != 0 && header.length < 8 {
+                return Err(Error::new(ErrorKind::Other, format!("checksummed message too short: {:?}", header)));
             }
+
+            Ok(Some(header))
         }
-        return None;
+        else { Ok(None) }
     }
 
-    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+    // returns a header when there is enough supporting data, and an error if the header is implausible.
+    pub fn try_read(bytes: &mut &[u8]) -> Result<Option<MessageHeader>> {
+        if let Some(header) = try!(MessageHeader::peek(bytes)) {
+            if bytes.len() as u64 >= HEADER_SIZE as u64 + header.length {
+                *bytes = &bytes[HEADER_SIZE..];
+                return Ok(Some(header));
+            }
+        }
+        return Ok(None);
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
         try!(writer.write_u64::<LittleEndian>(self.graph));
         try!(writer.write_u64::<LittleEndian>(self.channel));
         try!(writer.write_u64::<LittleEndian>(self.source));
         try!(writer.write_u64::<LittleEndian>(self.target));
         try!(writer.write_u64::<LittleEndian>(self.length));
+        try!(writer.write_u64::<LittleEndian>(self.flags));
         Ok(())
     }
 }
 
+// 64-bit FNV-1a, for detecting corrupted payloads
+pub fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for &byte in bytes.iter() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
 // structure in charge of receiving data from a Reader, for example the network
 struct BinaryReceiver<R: Read> {
     // targets (and u8 returns) indexed by worker, graph, and channel.
     // option because they get filled progressively; alt design might change that.
-    targets:    Vec<Vec<Vec<Option<(Sender<Vec<u8>>, Receiver<Vec<u8>>)>>>>,
+    targets:    Vec<Vec<Vec<Option<(Sender<(MessageHeader, Vec<u8>)>, Receiver<Vec<u8>>)>>>>,
+    peers:      u64,        // number of workers; targets beyond this indicate a corrupt stream
 
     reader:     R,          // the generic reader
-    buffer:     Vec<u8>,    // current working buffer
-    double:     Vec<u8>,    // second working buffer
+    window:     Vec<u8>,    // fixed-size window that bytes are read into
+    start:      usize,      // offset of the first unconsumed byte in window
+    end:        usize,      // offset just past the last valid byte in window
 
-    // how a BinaryReceiver learns about new channels; indices and corresponding channel pairs
-    channels:   Receiver<((u64, u64, u64), Sender<Vec<u8>>, Receiver<Vec<u8>>)>,
+    // how a BinaryReceiver learns about new channels; indices, tags, and corresponding channel pairs
+    channels:   Receiver<((u64, u64, u64), ChannelTag, Sender<(MessageHeader, Vec<u8>)>, Receiver<Vec<u8>>)>,
+
+    // what local workers and remote workers allocated each (target, graph, channel) for, which should agree
+    tags:       HashMap<(u64, u64, u64), ChannelTag>,                   // as registered by the local target
+    announced:  HashMap<(u64, u64, u64), Vec<(u64, ChannelTag)>>,       // (source, tag) awaiting registration
+
+    unclaimed:  Unclaimed,                          // messages for channels not yet registered
+    layouts:    Layouts,                            // the channels allocated by the first worker to report, per graph
+    failure:    Arc<Mutex<Option<Failure>>>,        // where dataflow mismatches are recorded
+
+    credits:    Sender<(MessageHeader, Vec<u8>)>,   // the BinarySender for this connection, to return credit to
+    liveness:   Arc<Liveness>,                      // shared with the heartbeat thread, for failure detection
+    acknowledged: bool,                             // the peer has acknowledged our goodbye
+    statistics: Arc<Mutex<NetworkStatistics>>,      // shared with the BinarySender of this connection
+    signals:    Vec<Signal>,                        // of each local worker, notified as messages are delivered to it
 }
 
+pub const DEFAULT_WINDOW:   usize = 1 << 20;    // bytes a BinaryReceiver reads into at a time
+
 impl<R: Read> BinaryReceiver<R> {
-    fn new(reader: R, targets: u64, channels: Receiver<((u64, u64, u64), Sender<Vec<u8>>, Receiver<Vec<u8>>)>) -> BinaryReceiver<R> {
+    fn new(reader: R,
+           peers: u64,
+           channels: Receiver<((u64, u64, u64), ChannelTag, Sender<(MessageHeader, Vec<u8>)>, Receiver<Vec<u8>>)>,
+           credits: Sender<(MessageHeader, Vec<u8>)>,
+           liveness: Arc<Liveness>,
+           statistics: Arc<Mutex<NetworkStatistics>>) -> BinaryReceiver<R> {
         BinaryReceiver {
-            targets:    (0..targets).map(|_| Vec::new()).collect(),
+            targets:    (0..peers).map(|_| Vec::new()).collect(),
+            peers:      peers,
             reader:     reader,
-            buffer:     Vec::new(),
-            double:     Vec::new(),
+            window:     repeat(0u8).take(DEFAULT_WINDOW).collect(),
+            start:      0,
+            end:        0,
             channels:   channels,
+            tags:       HashMap::new(),
+            announced:  HashMap::new(),
+            unclaimed:  Arc::new(Mutex::new(HashMap::new())),
+            layouts:    Arc::new(Mutex::new(HashMap::new())),
+            failure:    Arc::new(Mutex::new(None)),
+            credits:    credits,
+            liveness:   liveness,
+            acknowledged: false,
+            statistics: statistics,
+            signals:    Vec::new(),
         }
     }
 
-    fn recv_loop(&mut self) {
-        loop {
+    // window size in bytes; messages larger than half of it are read directly into their own buffers.
+    fn window(mut self, bytes: usize) -> BinaryReceiver<R> {
+        let bytes = if bytes < 2 * HEADER_SIZE { 2 * HEADER_SIZE } else { bytes };
+        self.window = repeat(0u8).take(bytes).collect();
+        self
+    }
 
-            // attempt to read some more bytes into our buffer
-            let valid = self.buffer.len();
-            self.buffer.reserve(1 << 20);
-            unsafe { self.buffer.set_len(valid + (1 << 20)); }
-            // println!("reading");
-            let read = self.reader.read(&mut self.buffer[valid..(valid + (1 << 20))]).unwrap_or(0);
-            // println!("read: {}", read);
+    // the signals of this process's workers, indexed by worker within the process, to wake them as messages arrive.
+    fn signals(mut self, signals: Vec<Signal>) -> BinaryReceiver<R> { self.signals = signals; self }
 
-            unsafe { self.buffer.set_len(valid + read); }
+    // where messages for unregistered channels are kept, shared with the workers of this process.
+    fn unclaimed(mut self, unclaimed: Unclaimed) -> BinaryReceiver<R> { self.unclaimed = unclaimed; self }
 
-            {
-                // get a view of available bytes
-                let mut slice = &self.buffer[..];
+    // where layouts are compared, shared with the workers of this process.
+    fn layouts(mut self, layouts: Layouts) -> BinaryReceiver<R> { self.layouts = layouts; self }
 
-                while let Some(header) = MessageHeader::try_read(&mut slice) {
-                    let h_tgt = header.target as usize;  // target worker
-                    let h_grp = header.graph as usize;   // target graph
-                    let h_chn = header.channel as usize; // target channel
-                    let h_len = header.length as usize;  // length in bytes
+    // where dataflow mismatches are recorded, rather than as a failure of the peer they were learned from.
+    fn failure(mut self, failure: Arc<Mutex<Option<Failure>>>) -> BinaryReceiver<R> { self.failure = failure; self }
 
-                    // println!("looking for {} bytes; have {} bytes", h_len, slice.len());
+    // records a mismatch, and stops the receiver: the computation cannot complete.
+    fn mismatch(&self, mismatch: DataflowMismatch) -> Error {
+        let error = Error::new(ErrorKind::Other, format!("{}", mismatch));
+        record_mismatch(&self.failure, mismatch);
+        error
+    }
 
-                    while self.targets.len() <= h_tgt { self.targets.push(Vec::new()) ;}
-                    while self.targets[h_tgt].len() <= h_grp { self.targets[h_tgt].push(Vec::new()) ;}
-                    while self.targets[h_tgt][h_grp].len() <= h_chn { self.targets[h_tgt][h_grp].push(None) ;}
+    // reads and delivers messages until the reader is exhausted, or until the peer has both said goodbye and
+    // acknowledged our goodbye; errors on a corrupt or truncated stream.
+    //
+    // messages are delivered straight out of the window, which is only compacted when reads reach its end,
+    // moving at most half a window of partial message to the front. a message too large for that is read
+    // directly into the buffer it is delivered in, so each of its bytes is copied once.
+    fn recv_loop(&mut self) -> Result<()> {
+        loop {
 
-                    // ensure that the destination exists!
-                    while let None = self.targets[h_tgt][h_grp][h_chn] {
-                        // receive channel descriptions if any
-                        let ((t, g, c), s, r) = self.channels.recv().unwrap();
-                        while self.targets.len() as u64 <= t { self.targets.push(Vec::new()); }
-                        while self.targets[t as usize].len() as u64 <= g { self.targets[t as usize].push(Vec::new()); }
-                        while self.targets[t as usize][g as usize].len() as u64 <= c { self.targets[t as usize][g as usize].push(None); }
+            // deliver each complete message, then make room for whatever message is incomplete
+            loop {
+                match try!(MessageHeader::peek(&self.window[self.start..self.end])) {
+                    Some(header) => {
+                        let extent = HEADER_SIZE + header.length as usize;
+                        if self.end - self.start >= extent {
+                            // the window is set aside so that deliver() may borrow self.
+                            let window = mem::replace(&mut self.window, Vec::new());
+                            let result = self.deliver(header, &window[self.start + HEADER_SIZE .. self.start + extent]);
+                            self.window = window;
+                            try!(result);
+                            self.start += extent;
+                        }
+                        else if extent > self.window.len() / 2 { try!(self.receive_large(header)); }
+                        else {
+                            if self.start + extent > self.window.len() { self.compact(); }
+                            break;
+                        }
 
-                        self.targets[t as usize][g as usize][c as usize] = Some((s, r));
-                    }
+                        if self.finished() { return Ok(()); }
+                    },
+                    None => {
+                        if self.start == self.end || self.end == self.window.len() { self.compact(); }
+                        break;
+                    },
+                }
+            }
 
-                    let mut buffer = if let Ok(b) = self.targets[h_tgt][h_grp][h_chn].as_ref().unwrap().1.try_recv() { b }
-                                     else { Vec::new() };
+            let read = try!(self.reader.read(&mut self.window[self.end..]));
+            if read == 0 {
+                return if self.start == self.end { Ok(()) }
+                       else { Err(Error::new(ErrorKind::Other, format!("stream ended mid-message, with {} bytes unconsumed", self.end - self.start))) };
+            }
+            self.end += read;
+            self.liveness.heard.store(now_ms(), Ordering::SeqCst);
+        }
+    }
 
-                    buffer.clear();
-                    buffer.push_all(&slice[..h_len]);
+    // the peer will send nothing more.
+    fn finished(&self) -> bool { self.acknowledged && self.liveness.farewell.load(Ordering::SeqCst) }
 
-                    slice = &slice[h_len..];
+    // moves unconsumed bytes to the front of the window.
+    fn compact(&mut self) {
+        let valid = self.end - self.start;
+        for index in (0..valid) { self.window[index] = self.window[self.start + index]; }
+        self.start = 0;
+        self.end = valid;
+    }
 
-                    self.targets[h_tgt][h_grp][h_chn].as_ref().unwrap().0.send(buffer).unwrap();
-                }
+    // reads the rest of a message that does not fit the window directly into the buffer it will be delivered in.
+    fn receive_large(&mut self, header: MessageHeader) -> Result<()> {
+        let length = header.length as usize;
+        let mut buffer = try!(self.buffer_for(&header));
+        buffer.push_all(&self.window[self.start + HEADER_SIZE .. self.end]);
+        self.start = 0;
+        self.end = 0;
 
-                // TODO: way inefficient... =/ Fix! :D
-                self.double.clear();
-                self.double.push_all(slice);
+        let mut filled = buffer.len();
+        while filled < length {
+            // grow as bytes arrive, so that a corrupt length cannot demand an enormous allocation up front
+            if filled == buffer.len() {
+                let grow = min(length - filled, max(filled, self.window.len()));
+                buffer.extend(repeat(0u8).take(grow));
+            }
+            let read = try!(self.reader.read(&mut buffer[filled..]));
+            if read == 0 {
+                return Err(Error::new(ErrorKind::Other, format!("stream ended mid-message, with {} of {} bytes received", filled, length)));
             }
+            filled += read;
+            self.liveness.heard.store(now_ms(), Ordering::SeqCst);
+        }
+
+        self.deliver_buffer(header, buffer)
+    }
+
+    fn deliver(&mut self, header: MessageHeader, payload: &[u8]) -> Result<()> {
+        let mut buffer = try!(self.buffer_for(&header));
+        buffer.push_all(payload);
+        self.deliver_buffer(header, buffer)
+    }
 
-            mem::swap(&mut self.buffer, &mut self.double);
+    // an empty buffer to receive the payload of `header` into, recycled from its destination if possible.
+    fn buffer_for(&mut self, header: &MessageHeader) -> Result<Vec<u8>> {
+
+        if header.target >= self.peers {
+            return Err(Error::new(ErrorKind::Other, format!("message for unknown worker: {:?}", header)));
         }
+
+        // grants are consumed by our BinarySender and other control messages by no one, so no buffer is returned
+        if header.flags & FLAG_CONTROL != 0 { return Ok(Vec::new()); }
+
+        // a channel not yet registered has no buffers to recycle
+        let mut buffer = if !self.registered((header.target, header.graph, header.channel)) { Vec::new() }
+                         else if let Ok(b) = self.targets[header.target as usize][header.graph as usize][header.channel as usize].as_ref().unwrap().1.try_recv() { b }
+                         else { Vec::new() };
+
+        buffer.clear();
+        Ok(buffer)
     }
 
-    fn ensure(&mut self, target: u64, graph: u64, channel: u64) {
-        // println!("starting ensure({}, {}, {})", target, graph, channel);
+    // checks and strips any checksum, then hands the payload to its destination.
+    fn deliver_buffer(&mut self, mut header: MessageHeader, mut buffer: Vec<u8>) -> Result<()> {
+
+        if header.flags & FLAG_CONTROL == 0 {
+            self.statistics.lock().ok().expect("mutex error?").record_received(&header);
+        }
+
+        if header.flags & FLAG_CHECKSUM != 0 {
+            let split = buffer.len() - 8;
+            if try!((&buffer[split..]).read_u64::<LittleEndian>()) != checksum(&buffer[..split]) {
+                return Err(Error::new(ErrorKind::Other, format!("checksum mismatch: {:?}", header)));
+            }
+            buffer.truncate(split);
+        }
+
+        // heartbeats have served their purpose by arriving
+        if header.flags & FLAG_HEARTBEAT != 0 { return Ok(()); }
+
+        // a goodbye is acknowledged by our BinarySender, and an acknowledgement of our goodbye just noted
+        if header.flags & FLAG_GOODBYE != 0 {
+            self.liveness.farewell.store(true, Ordering::SeqCst);
+            header.flags = FLAG_GOODBYE_ACK;
+            return self.credits.send((header, buffer)).map_err(|_| Error::new(ErrorKind::Other, "BinarySender hung up"));
+        }
+        if header.flags & FLAG_GOODBYE_ACK != 0 { self.acknowledged = true; return Ok(()); }
 
-        while self.targets.len() as u64 <= target { self.targets.push(Vec::new()); }
-        while self.targets[target as usize].len() as u64 <= graph { self.targets[target as usize].push(Vec::new()); }
-        while self.targets[target as usize][graph as usize].len() as u64 <= channel {
-            self.targets[target as usize][graph as usize].push(None);
+        if header.flags & FLAG_ANNOUNCE != 0 { return self.announce(&header, try!(ChannelTag::decode(&buffer[..]))); }
+        if header.flags & FLAG_LAYOUT != 0 {
+            let tags = try!(ChannelTag::decode_all(&buffer[..]));
+            return compare_layout(&self.layouts, header.graph, header.source, tags).map_err(|mismatch| self.mismatch(mismatch));
+        }
+
+        // grants return credit to our BinarySender, rather than data to a worker
+        if header.flags & FLAG_GRANT != 0 {
+            header.flags = FLAG_CREDIT;
+            return self.credits.send((header, buffer)).map_err(|_| Error::new(ErrorKind::Other, "BinarySender hung up"));
+        }
+
+        // a message for a channel its worker has yet to allocate is kept until the channel is registered. the
+        // registrations are checked again with the kept messages locked, as the worker claims them with a registration
+        // sent before it takes the lock; so each kept message is delivered by one of us, and before any later message.
+        let key = (header.target, header.graph, header.channel);
+        if !self.registered(key) {
+            let unclaimed = self.unclaimed.clone();
+            let mut unclaimed = unclaimed.lock().ok().expect("mutex error?");
+            while let Ok(registration) = self.channels.try_recv() { try!(self.register(registration, &mut unclaimed)); }
+            if !self.registered(key) {
+                unclaimed.entry(key).or_insert(Vec::new()).push((header, buffer));
+                return Ok(());
+            }
         }
 
-        while let None = self.targets[target as usize][graph as usize][channel as usize] {
-            // receive channel descriptions if any
-            let ((t, g, c), s, r) = self.channels.recv().unwrap();
-            while self.targets.len() as u64 <= t { self.targets.push(Vec::new()); }
-            while self.targets[t as usize].len() as u64 <= g { self.targets[t as usize].push(Vec::new()); }
-            while self.targets[t as usize][g as usize].len() as u64 <= c { self.targets[t as usize][g as usize].push(None); }
+        let h_tgt = header.target as usize;  // target worker
+        let h_grp = header.graph as usize;   // target graph
+        let h_chn = header.channel as usize; // target channel
 
-            self.targets[t as usize][g as usize][c as usize] = Some((s, r));
+        // a worker that has dropped its end of a channel is shutting down, and wants no more of its messages; those
+        // still in flight from a peer that has yet to hear of it are no fault of the peer, and are discarded.
+        if self.targets[h_tgt][h_grp][h_chn].as_ref().unwrap().0.send((header, buffer)).is_ok() {
+            if self.signals.len() > 0 { self.signals[h_tgt % self.signals.len()].notify(); }
         }
+        Ok(())
+    }
+
+    // whether the worker of (target, graph, channel) has registered it with us.
+    fn registered(&self, (target, graph, channel): (u64, u64, u64)) -> bool {
+        self.targets.get(target as usize).and_then(|graphs| graphs.get(graph as usize))
+                                         .and_then(|channels| channels.get(channel as usize))
+                                         .map(|entry| entry.is_some()).unwrap_or(false)
+    }
+
+    // takes any registrations that have arrived, without waiting for more.
+    fn poll_registrations(&mut self) -> Result<()> {
+        let unclaimed = self.unclaimed.clone();
+        let mut unclaimed = unclaimed.lock().ok().expect("mutex error?");
+        while let Ok(registration) = self.channels.try_recv() { try!(self.register(registration, &mut unclaimed)); }
+        Ok(())
+    }
+
+    // records a channel registered by a local worker, delivering any messages kept for it that the worker has not
+    // claimed, and checking it against what remote workers announced.
+    fn register(&mut self, ((t, g, c), tag, s, r): ((u64, u64, u64), ChannelTag, Sender<(MessageHeader, Vec<u8>)>, Receiver<Vec<u8>>),
+                unclaimed: &mut HashMap<(u64, u64, u64), Vec<(MessageHeader, Vec<u8>)>>) -> Result<()> {
+        while self.targets.len() as u64 <= t { self.targets.push(Vec::new()); }
+        while self.targets[t as usize].len() as u64 <= g { self.targets[t as usize].push(Vec::new()); }
+        while self.targets[t as usize][g as usize].len() as u64 <= c { self.targets[t as usize][g as usize].push(None); }
+        if let Some(messages) = unclaimed.remove(&(t, g, c)) {
+            for message in messages.into_iter() { s.send(message).ok(); }
+            if self.signals.len() > 0 { self.signals[t as usize % self.signals.len()].notify(); }
+        }
+        self.targets[t as usize][g as usize][c as usize] = Some((s, r));
+
+        if let Some(announcements) = self.announced.remove(&(t, g, c)) {
+            for (source, remote) in announcements.into_iter() {
+                try!(agree((t, g, c), &tag, source, &remote).map_err(|mismatch| self.mismatch(mismatch)));
+            }
+        }
+        self.tags.insert((t, g, c), tag);
+        Ok(())
+    }
+
+    // checks a remote worker's announcement of a channel against the local registration, or keeps it until then.
+    fn announce(&mut self, header: &MessageHeader, remote: ChannelTag) -> Result<()> {
+        // registrations that have already arrived are picked up, but not waited for
+        try!(self.poll_registrations());
+
+        let key = (header.target, header.graph, header.channel);
+        if let Some(local) = self.tags.get(&key) { return agree(key, local, header.source, &remote).map_err(|mismatch| self.mismatch(mismatch)); }
+        self.announced.entry(key).or_insert(Vec::new()).push((header.source, remote));
+        Ok(())
+    }
+}
 
-        // println!("exiting ensure()");
+// errors unless the local target of (target, graph, channel) and a remote source allocated it for the same thing.
+fn agree((target, graph, channel): (u64, u64, u64), local: &ChannelTag, source: u64, remote: &ChannelTag) -> ::std::result::Result<(), DataflowMismatch> {
+    if local == remote { Ok(()) }
+    else {
+        Err(DataflowMismatch {
+            graph:      graph,
+            channel:    channel,
+            workers:    (target, source),
+            tags:       (Some(local.clone()), Some(remote.clone())),
+        })
     }
 }
 
@@ -168,70 +701,295 @@ struct BinarySender<W: Write> {
     writer:     W,
     sources:    Receiver<(MessageHeader, Vec<u8>)>,
     buffers:    Vec<Vec<Vec<Option<Sender<Vec<u8>>>>>>,
-    channels:   Receiver<((u64, u64, u64), Sender<Vec<u8>>)>,
+    channels:   Receiver<((u64, u64, u64, u64), Sender<Vec<u8>>, Arc<AtomicUsize>)>,
+
+    // credit-based flow control, keyed by (graph, channel, source, target)
+    credits:    HashMap<(u64, u64, u64, u64), u64>,                                 // messages we may still send
+    outstanding:HashMap<(u64, u64, u64, u64), Arc<AtomicUsize>>,                    // shared with each BinaryPushable
+    pending:    HashMap<(u64, u64, u64, u64), VecDeque<(MessageHeader, Vec<u8>)>>,  // messages awaiting credit
+    backlog:    Arc<AtomicUsize>,                                                   // count of pending messages
+
+    // messages cleared to write; priority is always drained before bulk
+    priority:   VecDeque<(MessageHeader, Vec<u8>)>,
+    bulk:       VecDeque<(MessageHeader, Vec<u8>)>,
+
+    checksum:   bool,   // append a checksum to each payload
+    compress:   bool,   // compress payloads of at least COMPRESS_THRESHOLD bytes
+    compressed: Vec<u8>,// scratch space for compression
+
+    // shutdown: a goodbye is written once requested and nothing cleared is left to write, then only an acknowledgement
+    goodbye:    Option<MessageHeader>,  // requested goodbye, not yet written
+    said:       bool,                   // our goodbye has been written
+    acked:      bool,                   // the peer's goodbye has been acknowledged
+    farewell:   bool,                   // the peer has said goodbye, and will consume nothing more we send
+
+    statistics: Arc<Mutex<NetworkStatistics>>,  // shared with the BinaryReceiver of this connection
+    signals:    Vec<Signal>,                    // of each local worker, notified as its channels regain credit
 }
 
 impl<W: Write> BinarySender<W> {
     fn new(writer: W,
            targets: u64,
            sources: Receiver<(MessageHeader, Vec<u8>)>,
-           channels: Receiver<((u64, u64, u64), Sender<Vec<u8>>)>) -> BinarySender<W> {
+           channels: Receiver<((u64, u64, u64, u64), Sender<Vec<u8>>, Arc<AtomicUsize>)>,
+           backlog: Arc<AtomicUsize>,
+           checksum: bool,
+           compress: bool,
+           statistics: Arc<Mutex<NetworkStatistics>>) -> BinarySender<W> {
         BinarySender {
             writer:     writer,
             sources:    sources,
             buffers:    vec![Vec::new(); targets as usize],
             channels:   channels,
+            credits:    HashMap::new(),
+            outstanding:HashMap::new(),
+            pending:    HashMap::new(),
+            backlog:    backlog,
+            priority:   VecDeque::new(),
+            bulk:       VecDeque::new(),
+            checksum:   checksum,
+            compress:   compress,
+            compressed: Vec::new(),
+            goodbye:    None,
+            said:       false,
+            acked:      false,
+            farewell:   false,
+            statistics: statistics,
+            signals:    Vec::new(),
         }
     }
 
-    fn send_loop(&mut self) {
+    // the signals of this process's workers, indexed by worker within the process, to wake those held up by congestion.
+    fn signals(mut self, signals: Vec<Signal>) -> BinarySender<W> { self.signals = signals; self }
+
+    // writes messages until goodbyes have been exchanged, or all sources hang up; errors if the writer fails.
+    fn send_loop(&mut self) -> Result<()> {
         println!("send loop:\tstarting");
-        for (mut header, mut buffer) in self.sources.iter() {
-            // println!("send loop:\treceived data");
-            header.length = buffer.len() as u64;
-            // println!("sending {} bytes", header.length);
-            header.write_to(&mut self.writer).unwrap();
-            self.writer.write_all(&buffer[..]).unwrap();
-            buffer.clear();
-
-            // inline because borrow-checker hates me
-            let source = header.source as usize;
-            let graph = header.graph as usize;
-            let channel = header.channel as usize;
-
-            while self.buffers.len() <= source { self.buffers.push(Vec::new()); }
-            while self.buffers[source].len() <= graph { self.buffers[source].push(Vec::new()); }
-            while self.buffers[source][graph].len() <= channel {
-                self.buffers[source][graph].push(None);
+        loop {
+            if self.said && self.acked { return self.writer.flush(); }
+
+            // block for more input only when there is nothing cleared to write
+            if self.priority.len() == 0 && self.bulk.len() == 0 {
+
+                // a requested goodbye goes out once everything cleared to write is written
+                if self.goodbye.is_some() {
+                    let header = self.goodbye.take().unwrap();
+                    try!(self.write(header, Vec::new()));
+                    self.said = true;
+                    continue;
+                }
+
+                match self.sources.recv() {
+                    Ok((header, buffer)) => try!(self.accept(header, buffer)),
+                    Err(_)               => return Ok(()),
+                }
+            }
+            while let Ok((header, buffer)) = self.sources.try_recv() { try!(self.accept(header, buffer)); }
+
+            // strict priority: one bulk message is written only when no priority message is waiting
+            if let Some((header, buffer)) = self.priority.pop_front() { try!(self.write(header, buffer)); }
+            else if let Some((header, buffer)) = self.bulk.pop_front() { try!(self.write(header, buffer)); }
+        }
+    }
+
+    fn accept(&mut self, header: MessageHeader, buffer: Vec<u8>) -> Result<()> {
+        if header.flags & FLAG_CREDIT != 0 {
+            // the target has consumed some of our messages; return credit and release what we can.
+            let count = try!((&buffer[..]).read_u64::<LittleEndian>());
+            let key = (header.graph, header.channel, header.target, header.source);
+
+            // credit is only granted for messages we sent, and the BinaryPushable registered before pushing them, so
+            // its registration has arrived already; credit for anything else comes from a confused peer.
+            while let Ok(registration) = self.channels.try_recv() { self.registered(registration); }
+            if !self.credits.contains_key(&key) || !self.outstanding.contains_key(&key) {
+                return Err(Error::new(ErrorKind::Other, format!("credit for a channel never sent on: {:?}", header)));
             }
+            let credits = self.credits[&key].saturating_add(count);
+            self.credits.insert(key, credits);
+
+            // a peer granting more than was outstanding must not wrap the count around, and leave the channel
+            // congested for good.
+            {
+                let outstanding = &self.outstanding[&key];
+                let mut current = outstanding.load(Ordering::SeqCst);
+                loop {
+                    let previous = outstanding.compare_and_swap(current, current.saturating_sub(count as usize), Ordering::SeqCst);
+                    if previous == current { break; }
+                    current = previous;
+                }
+            }
+            if self.signals.len() > 0 { self.signals[header.target as usize % self.signals.len()].notify(); }
+            self.release(key);
+        }
+        else if header.flags & FLAG_GOODBYE != 0 {
+            // our workers are done; what awaits credit may wait on a peer itself waiting on us, so let it go.
+            self.goodbye = Some(header);
+            self.discard_pending();
+        }
+        else if header.flags & FLAG_GOODBYE_ACK != 0 {
+            // a goodbye received by our BinaryReceiver; acknowledge it to its sender
+            let ack = MessageHeader { source: header.target, target: header.source, length: 0, .. header };
+            self.priority.push_back((ack, Vec::new()));
+
+            // the peer's workers are done, and will grant no more credit; what awaits credit would wait forever,
+            // and hold back our own goodbye with it.
+            self.farewell = true;
+            self.discard_pending();
+        }
+        else if header.flags & FLAG_HEARTBEAT != 0 {
+            // after our goodbye the peer no longer listens for heartbeats
+            if !self.said { self.priority.push_back((header, buffer)); }
+        }
+        else if header.flags & (FLAG_GRANT | FLAG_ANNOUNCE | FLAG_LAYOUT) != 0 {
+            // grants and announcements are not themselves subject to flow control, and should not wait behind data.
+            // an announcement is accepted before any message of its channel, and so is written before them too.
+            self.priority.push_back((header, buffer));
+        }
+        else if !self.farewell && self.goodbye.is_none() && !self.said {
+            // data after either goodbye is dropped, as no one will read it
+            let key = (header.graph, header.channel, header.source, header.target);
+            if !self.credits.contains_key(&key) { self.credits.insert(key, DEFAULT_CREDITS); }
+            if !self.pending.contains_key(&key) { self.pending.insert(key, VecDeque::new()); }
+            self.pending.get_mut(&key).unwrap().push_back((header, buffer));
+            self.backlog.fetch_add(1, Ordering::SeqCst);
+            self.release(key);
+        }
+        Ok(())
+    }
+
+    // drops all messages awaiting credit, and releases them from the backlog
+    fn discard_pending(&mut self) {
+        let discarded = self.pending.values().fold(0, |sum, queue| sum + queue.len());
+        self.pending.clear();
+        self.backlog.fetch_sub(discarded, Ordering::SeqCst);
+    }
+
+    // clears pending messages for a channel to be written, while it has credit
+    fn release(&mut self, key: (u64, u64, u64, u64)) {
+        while self.credits[&key] > 0 {
+            if let Some((header, buffer)) = self.pending.get_mut(&key).and_then(|queue| queue.pop_front()) {
+                *self.credits.get_mut(&key).unwrap() -= 1;
+                self.backlog.fetch_sub(1, Ordering::SeqCst);
+                if header.flags & FLAG_PRIORITY != 0 { self.priority.push_back((header, buffer)); }
+                else                                 { self.bulk.push_back((header, buffer)); }
+            }
+            else { return; }
+        }
+    }
+
+    fn write(&mut self, mut header: MessageHeader, buffer: Vec<u8>) -> Result<()> {
+
+        // large payloads are sent compressed, if the connection negotiated it and it actually helps
+        let compressed = self.compress && buffer.len() >= COMPRESS_THRESHOLD && header.flags & FLAG_CONTROL == 0 && {
+            self.compressed.clear();
+            compress(&buffer[..], &mut self.compressed);
+            self.compressed.len() < buffer.len()
+        };
+        if compressed { header.flags |= FLAG_COMPRESSED; }
 
-            while let None = self.buffers[source][graph][channel] {
-                let ((t, g, c), s) = self.channels.recv().unwrap();
-                while self.buffers.len() as u64 <= t { self.buffers.push(Vec::new()); }
-                while self.buffers[t as usize].len() as u64 <= g { self.buffers[t as usize].push(Vec::new()); }
-                while self.buffers[t as usize][g as usize].len() as u64 <= c { self.buffers[t as usize][g as usize].push(None); }
-                self.buffers[t as usize][g as usize][c as usize] = Some(s);
+        {
+            let payload = if compressed { &self.compressed[..] } else { &buffer[..] };
+            header.length = payload.len() as u64;
+            if self.checksum {
+                header.flags |= FLAG_CHECKSUM;
+                header.length += 8;
             }
-            // end-inline
+            try!(header.write_to(&mut self.writer));
+            try!(self.writer.write_all(payload));
+            if self.checksum {
+                try!(self.writer.write_u64::<LittleEndian>(checksum(payload)));
+            }
+        }
+
+        if header.flags & FLAG_GOODBYE_ACK != 0 { self.acked = true; }
+
+        // buffers of control messages have no BinaryPushable to return to
+        if header.flags & FLAG_CONTROL == 0 {
+            self.statistics.lock().ok().expect("mutex error?").record_sent(&header);
+            try!(self.recycle(header, buffer));
+        }
+        Ok(())
+    }
+
+    // returns a written buffer to the BinaryPushable it came from
+    fn recycle(&mut self, header: MessageHeader, mut buffer: Vec<u8>) -> Result<()> {
+        buffer.clear();
 
-            self.buffers[source][graph][channel].as_ref().unwrap().send(buffer).unwrap();
+        // inline because borrow-checker hates me
+        let source = header.source as usize;
+        let graph = header.graph as usize;
+        let channel = header.channel as usize;
+
+        while self.buffers.len() <= source { self.buffers.push(Vec::new()); }
+        while self.buffers[source].len() <= graph { self.buffers[source].push(Vec::new()); }
+        while self.buffers[source][graph].len() <= channel {
+            self.buffers[source][graph].push(None);
         }
+
+        while let None = self.buffers[source][graph][channel] { try!(self.register()); }
+        // end-inline
+
+        // the worker may have finished with the channel, in which case it needs no buffers back
+        self.buffers[source][graph][channel].as_ref().unwrap().send(buffer).ok();
+        Ok(())
+    }
+
+    // takes the next channel registration of a BinaryPushable, waiting for one if need be.
+    fn register(&mut self) -> Result<()> {
+        let registration = try!(self.channels.recv().map_err(|_| Error::new(ErrorKind::Other, "no workers registering channels")));
+        self.registered(registration);
+        Ok(())
+    }
+
+    // records the channel registration of a BinaryPushable.
+    fn registered(&mut self, ((t, g, c, target), s, outstanding): ((u64, u64, u64, u64), Sender<Vec<u8>>, Arc<AtomicUsize>)) {
+        while self.buffers.len() as u64 <= t { self.buffers.push(Vec::new()); }
+        while self.buffers[t as usize].len() as u64 <= g { self.buffers[t as usize].push(Vec::new()); }
+        while self.buffers[t as usize][g as usize].len() as u64 <= c { self.buffers[t as usize][g as usize].push(None); }
+        self.buffers[t as usize][g as usize][c as usize] = Some(s);
+        self.outstanding.insert((g, c, t, target), outstanding);
     }
 }
 
-pub fn initialize_networking(addresses: Vec<String>, my_index: u64, workers: u64) -> Result<Vec<BinaryCommunicator>> {
+pub fn initialize_networking(addresses: Vec<String>, my_index: u64, workers: u64, fingerprint: u64) -> Result<Vec<BinaryCommunicator>> {
+    initialize_transport(TcpTransport::new(addresses, my_index), workers, fingerprint, false, false)
+}
 
-    let processes = addresses.len() as u64;
-    let hosts1 = Arc::new(addresses);
-    let hosts2 = hosts1.clone();
+// establishes the process mesh using the supplied transport, and starts a send and recv thread per connection.
+// fingerprint should identify the binary and dataflow; processes that disagree on it refuse to connect. the channels
+// workers allocate are compared separately, once their dataflows are built (see Communicator::allocation_complete).
+// checksum appends a checksum to each message sent, which receivers verify.
+// compress compresses large messages on connections to processes that also ask to compress.
+pub fn initialize_transport<T: Transport>(transport: T, workers: u64, fingerprint: u64, checksum: bool, compress: bool) -> Result<Vec<BinaryCommunicator>> {
 
-    let start_task = Future::spawn(move || start_connections(hosts1, my_index));
-    let await_task = Future::spawn(move || await_connections(hosts2, my_index));
+    let processes = transport.processes();
+    let my_index = transport.index();
 
-    let mut results = try!(await_task.into_inner());
+    let mut results = try!(transport.connect());
 
-    results.push(None);
-    results.append(&mut try!(start_task.into_inner()));
+    // confirm that each peer agrees with us about the computation before exchanging any messages.
+    let handshake = Handshake {
+        magic:          PROTOCOL_MAGIC,
+        version:        PROTOCOL_VERSION,
+        process:        my_index,
+        processes:      processes,
+        workers:        workers,
+        fingerprint:    fingerprint,
+        compress:       if compress { 1 } else { 0 },
+    };
+    for result in results.iter_mut() {
+        if let Some((_, ref mut writer)) = *result {
+            try!(handshake.write_to(writer));
+        }
+    }
+    let mut compressing = vec![false; results.len()];    // whether each connection compresses
+    for (index, result) in results.iter_mut().enumerate() {
+        if let Some((ref mut reader, _)) = *result {
+            let theirs = try!(Handshake::read_from(reader));
+            try!(handshake.verify(&theirs, index as u64));
+            compressing[index] = compress && theirs.compress == 1;
+        }
+    }
 
     println!("worker {}:\tinitialization complete", my_index);
 
@@ -239,32 +997,99 @@ pub fn initialize_networking(addresses: Vec<String>, my_index: u64, workers: u64
     let mut readers = Vec::new();   // handles to the BinaryReceivers (to present new channels)
     let mut senders = Vec::new();   // destinations for serialized data (to send serialized data)
 
-    // for each process, if a stream exists (i.e. not local) ...
+    let backlog = Arc::new(AtomicUsize::new(0));    // messages held back by BinarySenders for lack of credit
+    let failure = Arc::new(Mutex::new(None));       // the first peer failure observed by any connection
+    let unclaimed = Arc::new(Mutex::new(HashMap::new()));   // messages for channels workers have yet to allocate
+    let layouts = Arc::new(Mutex::new(HashMap::new()));     // the channels workers allocated, for comparison
+    let mut goodbyes = Vec::new();                  // for each BinarySender, a goodbye and where to send it
+    let mut threads = Vec::new();                   // networking threads, to join at shutdown
+    let mut statistics = Vec::new();                // traffic counts for each connection
+
+    let proc_comms = ProcessCommunicator::new_vector(workers);
+    let signals: Vec<Signal> = proc_comms.iter().map(|proc_comm| proc_comm.signal().unwrap()).collect();
+
+    // for each process, if a connection exists (i.e. not local) ...
     for index in (0..results.len()) {
-        if let Some(stream) = results[index].take() {
+        if let Some((reader, writer)) = results[index].take() {
             let (writer_channels_s, writer_channels_r) = channel();
             let (reader_channels_s, reader_channels_r) = channel();
             let (sender_channels_s, sender_channels_r) = channel();
 
             writers.push(writer_channels_s);    //
             readers.push(reader_channels_s);    //
-            senders.push(sender_channels_s);    //
+            senders.push(sender_channels_s.clone());
 
-            let mut sender = BinarySender::new(stream.try_clone().unwrap(), workers, sender_channels_r, writer_channels_r);
-            let mut recver = BinaryReceiver::new(stream.try_clone().unwrap(), workers, reader_channels_r);
+            let liveness = Arc::new(Liveness::new());
+            let traffic = Arc::new(Mutex::new(NetworkStatistics::default()));
+            statistics.push(traffic.clone());
+            let heartbeats = sender_channels_s.clone();
+            goodbyes.push((MessageHeader {
+                graph:      0,
+                channel:    0,
+                source:     my_index * workers,
+                target:     index as u64 * workers,
+                length:     0,
+                flags:      FLAG_GOODBYE,
+            }, sender_channels_s.clone()));
 
-            // start senders and receivers associated with this stream
-            thread::Builder::new().name(format!("send thread {}", index))
-                                  .spawn(move || sender.send_loop())
-                                  .unwrap();
-            thread::Builder::new().name(format!("recv thread {}", index))
-                                  .spawn(move || recver.recv_loop())
-                                  .unwrap();
+            let mut sender = BinarySender::new(writer, workers, sender_channels_r, writer_channels_r, backlog.clone(), checksum, compressing[index], traffic.clone()).signals(signals.clone());
+            let mut recver = BinaryReceiver::new(reader, workers * processes, reader_channels_r, sender_channels_s, liveness.clone(), traffic).signals(signals.clone()).unclaimed(unclaimed.clone()).layouts(layouts.clone()).failure(failure.clone());
+
+            // start senders and receivers associated with this connection, reporting any error as a failure of the peer
+            let send_failure = failure.clone();
+            threads.push(thread::Builder::new().name(format!("send thread {}", index))
+                                  .spawn(move || {
+                                      if let Err(error) = sender.send_loop() {
+                                          record_failure(&send_failure, index as u64, format!("error sending: {}", error));
+                                      }
+                                  })
+                                  .unwrap());
+            let recv_failure = failure.clone();
+            let recv_liveness = liveness.clone();
+            threads.push(thread::Builder::new().name(format!("recv thread {}", index))
+                                  .spawn(move || {
+                                      match recver.recv_loop() {
+                                          Ok(()) => if !recv_liveness.farewell.load(Ordering::SeqCst) {
+                                              record_failure(&recv_failure, index as u64, format!("connection closed without a goodbye"));
+                                          },
+                                          Err(error) => record_failure(&recv_failure, index as u64, format!("error receiving: {}", error)),
+                                      }
+                                  })
+                                  .unwrap());
+
+            // heartbeats keep an otherwise idle connection audible; a peer not heard from in a while is presumed failed.
+            let heartbeat = MessageHeader {
+                graph:      0,
+                channel:    0,
+                source:     my_index * workers,
+                target:     index as u64 * workers,
+                length:     0,
+                flags:      FLAG_HEARTBEAT,
+            };
+            let heartbeat_failure = failure.clone();
+            threads.push(thread::Builder::new().name(format!("heartbeat thread {}", index))
+                                  .spawn(move || {
+                                      loop {
+                                          sleep_ms(HEARTBEAT_INTERVAL_MS);
+                                          if liveness.farewell.load(Ordering::SeqCst) { return; }
+                                          let silence = now_ms().saturating_sub(liveness.heard.load(Ordering::SeqCst)) as u64;
+                                          if silence > HEARTBEAT_TIMEOUT_MS {
+                                              record_failure(&heartbeat_failure, index as u64, format!("not heard from in {}ms", silence));
+                                              return;
+                                          }
+                                          if heartbeats.send((heartbeat, Vec::new())).is_err() { return; }
+                                      }
+                                  })
+                                  .unwrap());
 
         }
     }
 
-    let proc_comms = ProcessCommunicator::new_vector(workers);
+    let network = Arc::new(NetworkThreads {
+        goodbyes:   Mutex::new(goodbyes),
+        threads:    Mutex::new(threads),
+        failure:    failure.clone(),
+    });
 
     let mut results = Vec::new();
     for (index, proc_comm) in proc_comms.into_iter().enumerate() {
@@ -277,47 +1102,441 @@ pub fn initialize_networking(addresses: Vec<String>, my_index: u64, workers: u64
             writers:        writers.clone(),
             readers:        readers.clone(),
             senders:        senders.clone(),
+            backlog:        backlog.clone(),
+            failure:        failure.clone(),
+            unclaimed:      unclaimed.clone(),
+            layouts:        layouts.clone(),
+            tags:           Vec::new(),
+            reported:       false,
+            statistics:     statistics.clone(),
+            aggregate:      false,
+            prioritize:     true,
+            network:        network.clone(),
         });
     }
 
     return Ok(results);
 }
 
-// result contains connections [0, my_index - 1].
-fn start_connections(addresses: Arc<Vec<String>>, my_index: u64) -> Result<Vec<Option<TcpStream>>> {
-    let mut results: Vec<_> = (0..my_index).map(|_| None).collect();
-    for index in (0..my_index) {
-        let mut connected = false;
-        while !connected {
-            match TcpStream::connect(&addresses[index as usize][..]) {
-                Ok(mut stream) => {
-                    try!(stream.write_u64::<LittleEndian>(my_index));
-                    results[index as usize] = Some(stream);
-                    println!("worker {}:\tconnection to worker {}", my_index, index);
-                    connected = true;
-                },
-                Err(error) => {
-                    println!("worker {}:\terror connecting to worker {}: {}; retrying", my_index, index, error);
-                    sleep_ms(1000);
-                },
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Result};
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::collections::HashMap;
+    use std::thread::{self, sleep_ms};
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    use communication::{Communicator, Pushable, ChannelTag};
+    use communication::simulation::XorShift;
+
+    use super::{MessageHeader, BinaryReceiver, BinarySender, Liveness, NetworkStatistics, ChannelStatistics};
+    use super::{Unclaimed, Layouts, Failure, compare_layout, checksum, initialize_transport};
+    use super::{FLAG_CHECKSUM, FLAG_HEARTBEAT, FLAG_ANNOUNCE, FLAG_LAYOUT, FLAG_GOODBYE, FLAG_GOODBYE_ACK, FLAG_CREDIT};
+    use super::{DEFAULT_WINDOW, HEADER_SIZE, DEFAULT_CREDITS, GRANT_BATCH};
+
+    // a reader yielding one byte per read, to exercise messages split across reads.
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            if self.0.len() > 0 && buf.len() > 0 { buf[0] = self.0[0]; self.0 = &self.0[1..]; Ok(1) }
+            else { Ok(0) }
+        }
+    }
+
+    fn frame(target: u64, payload: &[u8], checksummed: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let header = MessageHeader {
+            graph:      0,
+            channel:    0,
+            source:     1,
+            target:     target,
+            length:     payload.len() as u64 + if checksummed { 8 } else { 0 },
+            flags:      if checksummed { FLAG_CHECKSUM } else { 0 },
+        };
+        header.write_to(&mut bytes).unwrap();
+        bytes.push_all(payload);
+        if checksummed { bytes.write_u64::<LittleEndian>(checksum(payload)).unwrap(); }
+        bytes
+    }
+
+    // runs a BinaryReceiver with one registered channel over `reader`, returning its result and the payloads delivered.
+    fn receive<R: Read>(reader: R) -> (Result<()>, Vec<Vec<u8>>) { receive_windowed(reader, DEFAULT_WINDOW) }
+    fn receive_windowed<R: Read>(reader: R, window: usize) -> (Result<()>, Vec<Vec<u8>>) {
+        let (register_s, register_r) = channel();
+        let (credits_s, _credits_r) = channel();
+        let (data_s, data_r) = channel();
+        let (_return_s, return_r) = channel();
+        register_s.send(((0, 0, 0), ChannelTag::new::<u64>("test"), data_s, return_r)).unwrap();
+        drop(register_s);
+
+        let statistics = Arc::new(Mutex::new(NetworkStatistics::default()));
+        let result = BinaryReceiver::new(reader, 2, register_r, credits_s, Arc::new(Liveness::new()), statistics).window(window).recv_loop();
+        (result, data_r.iter().map(|(_, bytes)| bytes).collect())
+    }
+
+    #[test]
+    fn receiver_delivers_messages() {
+        let mut bytes = frame(0, &[1, 2, 3], false);
+        bytes.push_all(&frame(0, &[4, 5], true)[..]);
+        bytes.push_all(&frame(0, &[], false)[..]);
+
+        let expected = vec![vec![1, 2, 3], vec![4, 5], vec![]];
+        assert_eq!(receive(&bytes[..]).1, expected);
+        assert_eq!(receive(Trickle(&bytes[..])).1, expected);
+        assert!(receive(Trickle(&bytes[..])).0.is_ok());
+    }
+
+    #[test]
+    fn receiver_delivers_messages_larger_than_its_window() {
+        let small: Vec<u8> = (0..40).collect();
+        let large: Vec<u8> = (0..1000).map(|x| x as u8).collect();
+        let mut bytes = Vec::new();
+        for payload in [&small, &large, &small, &large, &large, &small].iter() {
+            bytes.push_all(&frame(0, &payload[..], true)[..]);
+        }
+
+        let expected = vec![small.clone(), large.clone(), small.clone(), large.clone(), large.clone(), small.clone()];
+        for &window in [96, 100, 256, 1024].iter() {
+            let (result, delivered) = receive_windowed(&bytes[..], window);
+            assert!(result.is_ok());
+            assert_eq!(delivered, expected);
+
+            let (result, delivered) = receive_windowed(Trickle(&bytes[..]), window);
+            assert!(result.is_ok());
+            assert_eq!(delivered, expected);
+        }
+    }
+
+    #[test]
+    fn receiver_discards_heartbeats() {
+        let mut bytes = Vec::new();
+        MessageHeader { graph: 0, channel: 0, source: 1, target: 0, length: 0, flags: FLAG_HEARTBEAT }.write_to(&mut bytes).unwrap();
+        bytes.push_all(&frame(0, &[1, 2, 3], false)[..]);
+
+        let (result, delivered) = receive(&bytes[..]);
+        assert!(result.is_ok());
+        assert_eq!(delivered, vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn receiver_counts_traffic_by_channel() {
+        let mut bytes = Vec::new();
+        MessageHeader { graph: 0, channel: 0, source: 1, target: 0, length: 0, flags: FLAG_HEARTBEAT }.write_to(&mut bytes).unwrap();
+        bytes.push_all(&frame(0, &[1, 2, 3], false)[..]);
+        bytes.push_all(&frame(0, &[4, 5], true)[..]);
+
+        let (register_s, register_r) = channel();
+        let (credits_s, _credits_r) = channel();
+        let (data_s, _data_r) = channel();
+        let (_return_s, return_r) = channel();
+        register_s.send(((0, 0, 0), ChannelTag::new::<u64>("test"), data_s, return_r)).unwrap();
+
+        let statistics = Arc::new(Mutex::new(NetworkStatistics::default()));
+        let result = BinaryReceiver::new(&bytes[..], 2, register_r, credits_s, Arc::new(Liveness::new()), statistics.clone()).recv_loop();
+        assert!(result.is_ok());
+
+        // heartbeats are not counted, but headers and checksums are
+        let statistics = statistics.lock().unwrap();
+        assert_eq!(statistics.received.len(), 1);
+        assert_eq!(statistics.received[&(0, 0, 1, 0)], ChannelStatistics { messages: 2, bytes: 2 * HEADER_SIZE as u64 + 3 + 2 + 8 });
+        assert_eq!(statistics.sent.len(), 0);
+
+        let mut merged = statistics.clone();
+        merged.merge(&statistics);
+        assert_eq!(merged.received[&(0, 0, 1, 0)].messages, 4);
+    }
+
+    #[test]
+    fn receiver_checks_channel_announcements() {
+        let announce = |tag: ChannelTag| {
+            let mut payload = Vec::new();
+            tag.encode(&mut payload);
+            let mut bytes = Vec::new();
+            MessageHeader { graph: 0, channel: 0, source: 1, target: 0, length: payload.len() as u64, flags: FLAG_ANNOUNCE }.write_to(&mut bytes).unwrap();
+            bytes.push_all(&payload[..]);
+            bytes
+        };
+
+        let mut bytes = announce(ChannelTag::new::<u64>("test"));
+        bytes.push_all(&frame(0, &[1, 2, 3], false)[..]);
+        let (result, delivered) = receive(&bytes[..]);
+        assert!(result.is_ok());
+        assert_eq!(delivered, vec![vec![1, 2, 3]]);
+
+        for tag in vec![ChannelTag::new::<u64>("other"), ChannelTag::new::<String>("test")].into_iter() {
+            let mut bytes = announce(tag);
+            bytes.push_all(&frame(0, &[1, 2, 3], false)[..]);
+            let (result, delivered) = receive(&bytes[..]);
+            assert!(result.is_err());
+            assert_eq!(delivered.len(), 0);
+        }
+    }
+
+    #[test]
+    fn receiver_keeps_messages_for_unallocated_channels() {
+        let mut bytes = frame(0, &[1, 2, 3], false);
+        bytes.push_all(&frame(0, &[4, 5], true)[..]);
+
+        // nothing is registered while the stream is read, as with a worker still building its dataflow
+        let (register_s, register_r) = channel();
+        let (credits_s, _credits_r) = channel();
+        let unclaimed: Unclaimed = Arc::new(Mutex::new(HashMap::new()));
+        let statistics = Arc::new(Mutex::new(NetworkStatistics::default()));
+        let mut receiver = BinaryReceiver::new(&bytes[..], 2, register_r, credits_s, Arc::new(Liveness::new()), statistics)
+                                          .unclaimed(unclaimed.clone());
+        assert!(receiver.recv_loop().is_ok());
+        assert_eq!(unclaimed.lock().unwrap()[&(0, 0, 0)].len(), 2);
+
+        // a later registration is given the messages, in the order they arrived
+        let (data_s, data_r) = channel();
+        let (_return_s, return_r) = channel();
+        register_s.send(((0, 0, 0), ChannelTag::new::<u64>("test"), data_s, return_r)).unwrap();
+        assert!(receiver.poll_registrations().is_ok());
+        assert_eq!(unclaimed.lock().unwrap().len(), 0);
+        assert_eq!((0..2).map(|_| data_r.try_recv().unwrap().1).collect::<Vec<_>>(), vec![vec![1, 2, 3], vec![4, 5]]);
+    }
+
+    #[test]
+    fn layouts_must_agree() {
+        let tags = vec![ChannelTag::new::<u64>("exchange"), ChannelTag::new::<String>("distinct")];
+        let mut bytes = Vec::new();
+        ChannelTag::encode_all(&tags[..], &mut bytes);
+        assert_eq!(ChannelTag::decode_all(&bytes[..]).unwrap(), tags);
+        assert!(ChannelTag::decode_all(&bytes[..bytes.len() - 1]).is_err());
+
+        let layouts: Layouts = Arc::new(Mutex::new(HashMap::new()));
+        assert!(compare_layout(&layouts, 0, 0, tags.clone()).is_ok());
+        assert!(compare_layout(&layouts, 0, 2, tags.clone()).is_ok());
+        assert!(compare_layout(&layouts, 1, 2, vec![]).is_ok());          // another graph
+
+        // a worker that allocated fewer channels, or different ones, disagrees with the first to report
+        let mismatch = compare_layout(&layouts, 0, 3, vec![tags[0].clone()]).unwrap_err();
+        assert_eq!((mismatch.graph, mismatch.channel, mismatch.workers), (0, 1, (0, 3)));
+        assert_eq!(mismatch.tags, (Some(tags[1].clone()), None));
+        assert!(format!("{}", mismatch).contains("nothing"));
+        assert!(compare_layout(&layouts, 0, 3, vec![tags[1].clone(), tags[0].clone()]).is_err());
+        assert!(compare_layout(&layouts, 0, 3, vec![tags[0].clone(), tags[1].clone(), tags[1].clone()]).is_err());
+
+        // the receiver compares a remote worker's layout on arrival, and reports a mismatch rather than a failed peer
+        let mut bytes = Vec::new();
+        MessageHeader { graph: 0, channel: 0, source: 1, target: 0, length: 0, flags: FLAG_LAYOUT }.write_to(&mut bytes).unwrap();
+        let (_register_s, register_r) = channel();
+        let (credits_s, _credits_r) = channel();
+        let failure = Arc::new(Mutex::new(None));
+        let statistics = Arc::new(Mutex::new(NetworkStatistics::default()));
+        let result = BinaryReceiver::new(&bytes[..], 2, register_r, credits_s, Arc::new(Liveness::new()), statistics)
+                                   .layouts(layouts.clone())
+                                   .failure(failure.clone())
+                                   .recv_loop();
+        assert!(result.is_err());
+        match failure.lock().unwrap().clone() {
+            Some(Failure::Mismatch(mismatch)) => { assert_eq!((mismatch.channel, mismatch.workers), (0, (0, 1))); },
+            other => panic!("expected a dataflow mismatch, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn receiver_finishes_after_goodbyes() {
+        let mut bytes = frame(0, &[1, 2, 3], false);
+        MessageHeader { graph: 0, channel: 0, source: 1, target: 0, length: 0, flags: FLAG_GOODBYE }.write_to(&mut bytes).unwrap();
+        MessageHeader { graph: 0, channel: 0, source: 1, target: 0, length: 0, flags: FLAG_GOODBYE_ACK }.write_to(&mut bytes).unwrap();
+        bytes.push_all(&[0xFF; 64]);     // nothing after the goodbyes is read
+
+        let (result, delivered) = receive(&bytes[..]);
+        assert!(result.is_ok());
+        assert_eq!(delivered, vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn receiver_rejects_truncated_streams() {
+        let first = frame(0, &[1, 2, 3, 4], true);
+        let mut bytes = first.clone();
+        bytes.push_all(&frame(0, &[5, 6, 7], false)[..]);
+
+        for cut in (1..bytes.len()) {
+            let (result, delivered) = receive_windowed(&bytes[..cut], 96);
+            if cut == first.len() {
+                assert!(result.is_ok());
+                assert_eq!(delivered, vec![vec![1, 2, 3, 4]]);
+            }
+            else {
+                assert!(result.is_err());
+                assert_eq!(delivered.len(), if cut < first.len() { 0 } else { 1 });
             }
         }
     }
 
-    return Ok(results);
-}
+    #[test]
+    fn receiver_rejects_corrupt_payloads() {
+        let bytes = frame(0, &[1, 2, 3, 4, 5, 6, 7, 8], true);
+        for position in (HEADER_SIZE..bytes.len()) {
+            let mut corrupt = bytes.clone();
+            corrupt[position] ^= 0x10;
+            let (result, delivered) = receive(&corrupt[..]);
+            assert!(result.is_err());
+            assert_eq!(delivered.len(), 0);
+        }
+    }
 
-// result contains connections [my_index + 1, addresses.len() - 1].
-fn await_connections(addresses: Arc<Vec<String>>, my_index: u64) -> Result<Vec<Option<TcpStream>>> {
-    let mut results: Vec<_> = (0..(addresses.len() - my_index as usize - 1)).map(|_| None).collect();
-    let listener = try!(TcpListener::bind(&addresses[my_index as usize][..]));
+    #[test]
+    fn receiver_rejects_implausible_headers() {
+        assert!(receive(&frame(2, &[1, 2, 3], false)[..]).0.is_err());     // target beyond the known workers
 
-    for _ in (my_index as usize + 1 .. addresses.len()) {
-        let mut stream = try!(listener.accept()).0;
-        let identifier = try!(stream.read_u64::<LittleEndian>()) as usize;
-        results[identifier - my_index as usize - 1] = Some(stream);
-        println!("worker {}:\tconnection from worker {}", my_index, identifier);
+        let mut bytes = frame(0, &[1, 2, 3], false);
+        bytes[32] = 0xFF; bytes[39] = 0xFF;                                 // absurd length
+        assert!(receive(&bytes[..]).0.is_err());
     }
 
-    return Ok(results);
+    #[test]
+    fn receiver_survives_random_streams() {
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        let valid = frame(0, &[1, 2, 3, 4, 5, 6, 7, 8, 9], true);
+
+        for _ in (0..1000) {
+            // entirely random bytes
+            let length = (rng.next() % 512) as usize;
+            let random: Vec<u8> = (0..length).map(|_| rng.next() as u8).collect();
+            let _ = receive_windowed(&random[..], 128);
+
+            // a valid message with random corruption, truncation, and trailing garbage
+            let mut mutated = valid.clone();
+            for _ in (0..(rng.next() % 4)) {
+                let position = (rng.next() % mutated.len() as u64) as usize;
+                mutated[position] = rng.next() as u8;
+            }
+            mutated.truncate((rng.next() % (valid.len() as u64 + 1)) as usize);
+            for _ in (0..(rng.next() % 64)) { mutated.push(rng.next() as u8); }
+            let _ = receive_windowed(Trickle(&mutated[..]), 128);
+        }
+    }
+
+    #[test]
+    fn sender_checks_credit() {
+        let (_sources_s, sources_r) = channel();
+        let (channels_s, channels_r) = channel();
+        let statistics = Arc::new(Mutex::new(NetworkStatistics::default()));
+        let mut sender = BinarySender::new(Vec::new(), 2, sources_r, channels_r, Arc::new(AtomicUsize::new(0)), false, false, statistics);
+        let credit = |count: u64| {
+            let mut buffer = Vec::new();
+            buffer.write_u64::<LittleEndian>(count).unwrap();
+            (MessageHeader { graph: 0, channel: 0, source: 1, target: 0, length: 8, flags: FLAG_CREDIT }, buffer)
+        };
+
+        // credit for a channel nothing was sent on is an error, rather than a wait for a registration that never comes
+        let (header, buffer) = credit(1);
+        assert!(sender.accept(header, buffer).is_err());
+
+        // credit for more than was sent leaves nothing outstanding, rather than wrapping around
+        let outstanding = Arc::new(AtomicUsize::new(1));
+        let (buffer_s, _buffer_r) = channel();
+        channels_s.send(((0, 0, 0, 1), buffer_s, outstanding.clone())).unwrap();
+        sender.accept(MessageHeader { graph: 0, channel: 0, source: 0, target: 1, length: 8, flags: 0 }, vec![0; 8]).unwrap();
+        let (header, buffer) = credit(5);
+        sender.accept(header, buffer).unwrap();
+        assert_eq!(outstanding.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn congestion_caps_messages_awaiting_a_slow_consumer() {
+        use communication::Pullable;
+        use networking::transport::PipeTransport;
+
+        let mut transports = PipeTransport::new_vector(2).unwrap();
+        let consumer_transport = transports.pop().unwrap();
+        let producer_transport = transports.pop().unwrap();
+        let (pushed_s, pushed_r) = channel();   // how many messages the producer has pushed
+        let (pulled_s, pulled_r) = channel();   // the consumer has pulled a batch
+
+        let producer = thread::spawn(move || {
+            let mut communicator = initialize_transport(producer_transport, 1, 0, false, false).unwrap().pop().unwrap();
+            let (mut pushers, _) = communicator.new_channel::<u64>("congestion");
+
+            // the consumer pulls nothing yet, so pushes stop at the credit the channel starts with, and none wait for more
+            let mut pushed = 0;
+            while !pushers[1].congested() { pushers[1].push(pushed); pushed += 1; }
+            assert_eq!(pushed, DEFAULT_CREDITS);
+            assert_eq!(communicator.backlog(), 0);
+            pushed_s.send(pushed).unwrap();
+
+            // each batch the consumer pulls lets as many more through, and no more
+            pulled_r.recv().unwrap();
+            while pushers[1].congested() { sleep_ms(1); }
+            while !pushers[1].congested() { pushers[1].push(pushed); pushed += 1; }
+            assert_eq!(pushed, DEFAULT_CREDITS + GRANT_BATCH);
+            assert_eq!(communicator.backlog(), 0);
+            pushed_s.send(pushed).unwrap();
+        });
+
+        let consumer = thread::spawn(move || {
+            let mut communicator = initialize_transport(consumer_transport, 1, 0, false, false).unwrap().pop().unwrap();
+            let (_, mut puller) = communicator.new_channel::<u64>("congestion");
+
+            let mut pulled = 0;
+            pushed_r.recv().unwrap();
+            while pulled < GRANT_BATCH { if puller.pull().is_some() { pulled += 1; } else { sleep_ms(1); } }
+            pulled_s.send(()).unwrap();
+            let pushed = pushed_r.recv().unwrap();
+            while pulled < pushed { if puller.pull().is_some() { pulled += 1; } else { sleep_ms(1); } }
+        });
+
+        producer.join().unwrap();
+        consumer.join().unwrap();
+    }
+
+    #[test]
+    fn shutdown_discards_messages_in_flight() {
+        use communication::Pullable;
+        use networking::transport::PipeTransport;
+
+        let mut transports = PipeTransport::new_vector(2).unwrap();
+        let consumer_transport = transports.pop().unwrap();
+        let producer_transport = transports.pop().unwrap();
+        let (pulled_s, pulled_r) = channel();   // the consumer has pulled what it wants, and is shutting down
+        let (failure_s, failure_r) = channel(); // each process's record of failures, to inspect after shutdown
+
+        let producer = thread::spawn(move || {
+            let mut communicator = initialize_transport(producer_transport, 1, 0, false, false).unwrap().pop().unwrap();
+            failure_s.send(communicator.failure.clone()).unwrap();
+            let (mut pushers, _) = communicator.new_channel::<u64>("in flight");
+
+            // more than the channel has credit for, so some wait on credit the consumer never grants
+            for record in (0..2 * DEFAULT_CREDITS) { pushers[1].push(record); }
+            pulled_r.recv().unwrap();
+            for record in (0..DEFAULT_CREDITS) { pushers[1].push(record); }
+        });
+
+        let consumer = thread::spawn(move || {
+            let mut communicator = initialize_transport(consumer_transport, 1, 0, false, false).unwrap().pop().unwrap();
+            let (_, mut puller) = communicator.new_channel::<u64>("in flight");
+            while puller.pull().is_none() { sleep_ms(1); }
+            drop(puller);
+            pulled_s.send(()).unwrap();
+            communicator.failure.clone()
+        });
+
+        // both processes shut down, neither blaming the other
+        let consumer_failure = consumer.join().unwrap();
+        producer.join().unwrap();
+        let producer_failure = failure_r.recv().unwrap();
+        assert!(consumer_failure.lock().unwrap().is_none());
+        assert!(producer_failure.lock().unwrap().is_none());
+
+        // both processes hold messages awaiting credit the other never grants; neither goodbye may wait on them.
+        let mut handles = Vec::new();
+        for transport in PipeTransport::new_vector(2).unwrap() {
+            handles.push(thread::spawn(move || {
+                let mut communicator = initialize_transport(transport, 1, 0, false, false).unwrap().pop().unwrap();
+                let (mut pushers, _puller) = communicator.new_channel::<u64>("in flight");
+                let peer = 1 - communicator.index() as usize;
+                for record in (0..2 * DEFAULT_CREDITS) { pushers[peer].push(record); }
+                communicator.failure.clone()
+            }));
+        }
+        for handle in handles {
+            assert!(handle.join().unwrap().lock().unwrap().is_none());
+        }
+    }
 }
@@ -0,0 +1,379 @@
+use std::io::{Read, Write, Result, Error, ErrorKind};
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, sleep_ms};
+use std::os::unix::io::AsRawFd;
+use std::cmp::min;
+use std::ptr;
+
+use libc;
+use time;
+
+use networking::transport::Transport;
+use networking::networking::{HEARTBEAT_INTERVAL_MS, HEARTBEAT_TIMEOUT_MS};
+
+// ring buffer header: bytes ever written and bytes ever read, on separate cache lines, then which ends have closed
+// the ring and a heartbeat counter for each end, so that either end can tell a departed peer from a slow one.
+const HEAD_OFFSET:  usize = 0;
+const TAIL_OFFSET:  usize = 64;
+const CLOSED_OFFSET:usize = 128;
+const WRITER_OFFSET:usize = 192;
+const READER_OFFSET:usize = 200;
+const DATA_OFFSET:  usize = 256;
+
+const WRITER_CLOSED:usize = 1;
+const READER_CLOSED:usize = 2;
+
+pub const DEFAULT_CAPACITY: usize = 1 << 24;
+
+// a blocked end yields this many times before sleeping between checks, and checks its peer is alive as it sleeps.
+// each process advances the heartbeat of its ends every HEARTBEAT_INTERVAL_MS, from zero once it has mapped the
+// ring; a peer whose heartbeat stands still for HEARTBEAT_TIMEOUT_MS is presumed to have exited.
+const SPIN_LIMIT:   usize = 1000;
+const SLEEP_MS:     u32 = 1;
+
+// Connections between processes on one host through shared memory, one mmap'd ring buffer per direction.
+//
+// Rendezvous: each process creates a ring file for each of its peers under `directory`, initializes it
+// under a temporary name, and renames it to `{source}-{target}.ring` to publish it. Each process then
+// waits for the rings its peers publish to it, maps them, and unlinks them; the mapping outlives the
+// file, so no files remain once all processes are connected, and the directory is removed by the last
+// process out. The directory should be unique to the run, so that rings from an earlier run are not
+// mistaken for current ones.
+//
+// This is a Transport rather than a Communicator of its own: initialize_transport puts a BinaryCommunicator over
+// the rings, as it does over sockets, so that serialized Columnar batches move through shared memory with the
+// same framing, credit and failure reporting as the other transports, rather than a second implementation of them.
+pub struct ShmTransport {
+    directory:  PathBuf,
+    processes:  u64,
+    index:      u64,
+    capacity:   usize,
+    deadline:   u64,        // milliseconds to wait for peers' rings, after which the missing peers are reported
+}
+
+impl ShmTransport {
+    pub fn new(directory: PathBuf, processes: u64, index: u64) -> ShmTransport {
+        ShmTransport { directory: directory, processes: processes, index: index, capacity: DEFAULT_CAPACITY, deadline: 60000 }
+    }
+
+    // ring capacity in bytes; larger rings let writers run further ahead of readers.
+    pub fn capacity(mut self, capacity: usize) -> ShmTransport { self.capacity = capacity; self }
+
+    pub fn deadline(mut self, deadline_ms: u64) -> ShmTransport { self.deadline = deadline_ms; self }
+
+    fn ring_path(&self, source: u64, target: u64) -> PathBuf {
+        self.directory.join(&format!("{}-{}.ring", source, target))
+    }
+}
+
+impl Transport for ShmTransport {
+    type Reader = RingReader;
+    type Writer = RingWriter;
+
+    fn index(&self) -> u64 { self.index }
+    fn processes(&self) -> u64 { self.processes }
+
+    fn connect(self) -> Result<Vec<Option<(RingReader, RingWriter)>>> {
+        try!(fs::create_dir_all(&self.directory));
+
+        // if connecting fails, our rings are removed, and the directory too if nothing else is left in it.
+        let mut cleanup = Cleanup { directory: self.directory.clone(), paths: Vec::new() };
+
+        // create and publish outgoing rings.
+        let mut writers: Vec<Option<RingWriter>> = (0..self.processes).map(|_| None).collect();
+        let mut beating = Vec::new();   // our ends of each ring, whose heartbeats we advance until they close
+        for target in (0..self.processes) {
+            if target != self.index {
+                let path = self.ring_path(self.index, target);
+                let temp = self.directory.join(&format!(".{}-{}.ring", self.index, target));
+                cleanup.paths.push(temp.clone());
+                cleanup.paths.push(path.clone());
+                let file = try!(OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&temp));
+                try!(file.set_len((DATA_OFFSET + self.capacity) as u64));
+                let mapping = Arc::new(try!(Mapping::new(&file, DATA_OFFSET + self.capacity)));
+                mapping.word(WRITER_OFFSET).store(1, Ordering::SeqCst);
+                try!(fs::rename(&temp, &path));
+                beating.push((mapping.clone(), WRITER_CLOSED, WRITER_OFFSET));
+                writers[target as usize] = Some(RingWriter { mapping: mapping, peer: Heartbeat::new(READER_OFFSET) });
+            }
+        }
+
+        // map and unlink incoming rings, as they are published.
+        let start = time::precise_time_ns();
+        let mut results = Vec::new();
+        for source in (0..self.processes) {
+            if source != self.index {
+                let path = self.ring_path(source, self.index);
+                let mut file = None;
+                while file.is_none() {
+                    match OpenOptions::new().read(true).write(true).open(&path) {
+                        Ok(opened) => { file = Some(opened); },
+                        Err(ref error) if error.kind() == ErrorKind::NotFound => {
+                            if (time::precise_time_ns() - start) / 1_000_000 >= self.deadline {
+                                let missing: Vec<u64> = (source..self.processes).filter(|&other| other != self.index && !self.ring_path(other, self.index).exists()).collect();
+                                return Err(Error::new(ErrorKind::Other, format!("process {}: no ring from processes {:?} within {}ms",
+                                                                                self.index, missing, self.deadline)));
+                            }
+                            println!("worker {}:\twaiting for ring from worker {}", self.index, source);
+                            sleep_ms(100);
+                        },
+                        Err(error) => { return Err(error); },
+                    }
+                }
+                let file = file.unwrap();
+                let length = try!(file.metadata()).len() as usize;
+                if length <= DATA_OFFSET {
+                    return Err(Error::new(ErrorKind::Other, format!("process {}: ring from process {} is too short", self.index, source)));
+                }
+                let mapping = Arc::new(try!(Mapping::new(&file, length)));
+                mapping.word(READER_OFFSET).store(1, Ordering::SeqCst);
+                try!(fs::remove_file(&path));
+                beating.push((mapping.clone(), READER_CLOSED, READER_OFFSET));
+                let writer = writers[source as usize].take().unwrap();
+                results.push(Some((RingReader { mapping: mapping, peer: Heartbeat::new(WRITER_OFFSET) }, writer)));
+                println!("worker {}:\tconnection with worker {}", self.index, source);
+            }
+            else { results.push(None); }
+        }
+
+        // our rings are left for peers to unlink, and the directory for the last of us.
+        cleanup.paths.clear();
+
+        // the heartbeat of an end stops once it closes the ring, which its peer checks for first; the thread exits
+        // once every end has closed.
+        try!(thread::Builder::new().name(format!("shm heartbeat thread")).spawn(move || {
+            while beating.len() > 0 {
+                sleep_ms(HEARTBEAT_INTERVAL_MS);
+                beating.retain(|&(ref mapping, end, _)| mapping.closed() & end == 0);
+                for &(ref mapping, _, offset) in beating.iter() { mapping.word(offset).fetch_add(1, Ordering::SeqCst); }
+            }
+        }));
+
+        Ok(results)
+    }
+}
+
+// on dropping, removes files and then the directory, which fails harmlessly while peers have files left in it.
+struct Cleanup {
+    directory:  PathBuf,
+    paths:      Vec<PathBuf>,
+}
+
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        for path in self.paths.iter() { fs::remove_file(path).ok(); }
+        fs::remove_dir(&self.directory).ok();
+    }
+}
+
+// a shared mapping of a whole file, unmapped when dropped.
+struct Mapping {
+    ptr:    *mut u8,
+    len:    usize,
+}
+
+unsafe impl Send for Mapping { }
+unsafe impl Sync for Mapping { }     // shared words are atomics, and the data is only touched by the end owning it
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> Result<Mapping> {
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len as libc::size_t,
+                                      libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED,
+                                      file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED { Err(Error::last_os_error()) }
+        else { Ok(Mapping { ptr: ptr as *mut u8, len: len }) }
+    }
+
+    fn word(&self, offset: usize) -> &AtomicUsize { unsafe { &*(self.ptr.offset(offset as isize) as *const AtomicUsize) } }
+    fn head(&self) -> &AtomicUsize { self.word(HEAD_OFFSET) }
+    fn tail(&self) -> &AtomicUsize { self.word(TAIL_OFFSET) }
+    fn closed(&self) -> usize { self.word(CLOSED_OFFSET).load(Ordering::SeqCst) }
+    fn close(&self, end: usize) { self.word(CLOSED_OFFSET).fetch_or(end, Ordering::SeqCst); }
+    fn capacity(&self) -> usize { self.len - DATA_OFFSET }
+
+    fn data(&self, offset: usize) -> *mut u8 { unsafe { self.ptr.offset((DATA_OFFSET + offset) as isize) } }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) { unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len as libc::size_t); } }
+}
+
+// what an end has seen of its peer's heartbeat. a process that dies cannot set its closed bit, but its heartbeat
+// stops; a process id would not do, as the id of an exited process may be reused by another.
+struct Heartbeat {
+    offset: usize,  // the peer's heartbeat word
+    seen:   usize,  // its value when last checked
+    since:  u64,    // when it last changed, in milliseconds
+}
+
+impl Heartbeat {
+    fn new(offset: usize) -> Heartbeat { Heartbeat { offset: offset, seen: 0, since: time::precise_time_ns() / 1_000_000 } }
+
+    // whether the peer, if it has mapped the ring yet, has exited.
+    fn departed(&mut self, mapping: &Mapping) -> bool {
+        let beat = mapping.word(self.offset).load(Ordering::SeqCst);
+        let now = time::precise_time_ns() / 1_000_000;
+        if beat != self.seen { self.seen = beat; self.since = now; }
+        beat != 0 && now - self.since > HEARTBEAT_TIMEOUT_MS
+    }
+}
+
+// the producing end of a ring; blocks while the ring is full, spinning briefly and then sleeping. writing fails once
+// the reader has closed the ring or exited, as nothing more would be read.
+pub struct RingWriter {
+    mapping:    Arc<Mapping>,
+    peer:       Heartbeat,      // the reader's
+}
+
+impl Write for RingWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let capacity = self.mapping.capacity();
+        let mut waits = 0;
+        loop {
+            if self.mapping.closed() & READER_CLOSED != 0 {
+                return Err(Error::new(ErrorKind::BrokenPipe, "ring closed by its reader"));
+            }
+            let head = self.mapping.head().load(Ordering::Relaxed);     // only we write head
+            let tail = self.mapping.tail().load(Ordering::Acquire);
+            let free = capacity - (head - tail);
+            if free > 0 {
+                let count = min(free, buf.len());
+                let offset = head % capacity;
+                let first = min(count, capacity - offset);
+                unsafe {
+                    ptr::copy_nonoverlapping(buf.as_ptr(), self.mapping.data(offset), first);
+                    ptr::copy_nonoverlapping(buf.as_ptr().offset(first as isize), self.mapping.data(0), count - first);
+                }
+                self.mapping.head().store(head + count, Ordering::Release);
+                return Ok(count);
+            }
+            let (mapping, peer) = (&self.mapping, &mut self.peer);
+            if wait(&mut waits, || peer.departed(mapping)) {
+                return Err(Error::new(ErrorKind::BrokenPipe, "ring reader exited"));
+            }
+        }
+    }
+    fn flush(&mut self) -> Result<()> { Ok(()) }
+}
+
+impl Drop for RingWriter {
+    fn drop(&mut self) { self.mapping.close(WRITER_CLOSED); }
+}
+
+// the consuming end of a ring; blocks while the ring is empty, spinning briefly and then sleeping. once the writer
+// has closed the ring and it is empty, reads return end-of-stream; if the writer exited without closing it, an error.
+pub struct RingReader {
+    mapping:    Arc<Mapping>,
+    peer:       Heartbeat,      // the writer's
+}
+
+impl Read for RingReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let capacity = self.mapping.capacity();
+        let mut waits = 0;
+        loop {
+            // closed is read before head, so that everything written before the ring was closed is read.
+            let closed = self.mapping.closed() & WRITER_CLOSED != 0;
+            let tail = self.mapping.tail().load(Ordering::Relaxed);     // only we write tail
+            let head = self.mapping.head().load(Ordering::Acquire);
+            let available = head - tail;
+            if available > 0 {
+                let count = min(available, buf.len());
+                let offset = tail % capacity;
+                let first = min(count, capacity - offset);
+                unsafe {
+                    ptr::copy_nonoverlapping(self.mapping.data(offset), buf.as_mut_ptr(), first);
+                    ptr::copy_nonoverlapping(self.mapping.data(0), buf.as_mut_ptr().offset(first as isize), count - first);
+                }
+                self.mapping.tail().store(tail + count, Ordering::Release);
+                return Ok(count);
+            }
+            if closed { return Ok(0); }
+            let (mapping, peer) = (&self.mapping, &mut self.peer);
+            if wait(&mut waits, || peer.departed(mapping)) {
+                return Err(Error::new(ErrorKind::BrokenPipe, "ring writer exited without closing the ring"));
+            }
+        }
+    }
+}
+
+impl Drop for RingReader {
+    fn drop(&mut self) { self.mapping.close(READER_CLOSED); }
+}
+
+// waits a little before an end checks its ring again: yielding for the first SPIN_LIMIT waits, and then sleeping
+// and checking whether the peer has departed, which is returned.
+fn wait<F: FnMut()->bool>(waits: &mut usize, mut departed: F) -> bool {
+    *waits += 1;
+    if *waits < SPIN_LIMIT { thread::yield_now(); false }
+    else { sleep_ms(SLEEP_MS); departed() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write, ErrorKind};
+    use std::thread;
+    use std::sync::atomic::Ordering;
+    use time;
+
+    use networking::transport::Transport;
+    use networking::networking::HEARTBEAT_TIMEOUT_MS;
+    use super::{ShmTransport, Mapping, Heartbeat, READER_OFFSET, DATA_OFFSET};
+
+    #[test]
+    fn closed_rings_end_streams() {
+        use std::env;
+
+        let directory = env::temp_dir().join(&format!("timely-shm-test-{}", time::precise_time_ns()));
+        let other = directory.clone();
+        let peer = thread::spawn(move || ShmTransport::new(other, 2, 1).capacity(1024).connect().unwrap());
+        let mut ours = ShmTransport::new(directory.clone(), 2, 0).capacity(1024).connect().unwrap();
+        let mut theirs = peer.join().unwrap();
+        let (mut reader, mut writer) = ours[1].take().unwrap();
+        let (their_reader, mut their_writer) = theirs[0].take().unwrap();
+
+        // what was written before the writer closed the ring is read, and then the end of the stream
+        their_writer.write_all(b"goodbye").unwrap();
+        drop(their_writer);
+        let mut buffer = [0u8; 16];
+        assert_eq!(reader.read(&mut buffer).unwrap(), 7);
+        assert_eq!(&buffer[..7], b"goodbye");
+        assert_eq!(reader.read(&mut buffer).unwrap(), 0);
+
+        // once the reader has closed the ring, writing fails rather than waits for space
+        drop(their_reader);
+        assert_eq!(writer.write(b"hello").err().unwrap().kind(), ErrorKind::BrokenPipe);
+
+        // connecting unlinked the rings and removed the directory
+        assert!(!directory.exists());
+    }
+
+    #[test]
+    fn silent_peers_depart() {
+        use std::env;
+        use std::fs::{self, OpenOptions};
+
+        let path = env::temp_dir().join(&format!("timely-shm-heartbeat-{}", time::precise_time_ns()));
+        let file = OpenOptions::new().read(true).write(true).create(true).open(&path).unwrap();
+        file.set_len(DATA_OFFSET as u64 + 64).unwrap();
+        let mapping = Mapping::new(&file, DATA_OFFSET + 64).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // a peer that has not mapped the ring yet is waited for, however long it takes
+        let mut peer = Heartbeat::new(READER_OFFSET);
+        peer.since -= HEARTBEAT_TIMEOUT_MS + 1;
+        assert!(!peer.departed(&mapping));
+
+        // a beating peer is alive, and one whose heartbeat stands still for long enough is not
+        mapping.word(READER_OFFSET).store(1, Ordering::SeqCst);
+        assert!(!peer.departed(&mapping));
+        assert!(!peer.departed(&mapping));
+        peer.since -= HEARTBEAT_TIMEOUT_MS + 1;
+        assert!(peer.departed(&mapping));
+        mapping.word(READER_OFFSET).fetch_add(1, Ordering::SeqCst);
+        assert!(!peer.departed(&mapping));
+    }
+}
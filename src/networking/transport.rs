@@ -0,0 +1,500 @@
+use std::io::{Read, Write, Result, Error, ErrorKind};
+use std::thread::{self, sleep_ms};
+use std::sync::Future;
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::cmp::min;
+use std::default::Default;
+use std::fs;
+
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::RawFd;
+use unix_socket::{UnixListener, UnixStream};
+use libc;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use time;
+
+use networking::config::{NetworkConfig, Retry};
+
+// identifies the deadline alarm, rather than a peer, to await_connections.
+const WAKEUP: u64 = !0;
+
+// A Transport establishes the byte streams connecting this process to each of its peers.
+// BinarySender<W: Write> and BinaryReceiver<R: Read> only need the two halves of each connection,
+// so anything that can produce a (Read, Write) pair per peer process can back a BinaryCommunicator.
+pub trait Transport {
+    type Reader: Read+Send+'static;
+    type Writer: Write+Send+'static;
+
+    fn index(&self) -> u64;         // index of this process
+    fn processes(&self) -> u64;     // number of processes
+
+    // connections to each process, indexed by process; None for this process.
+    fn connect(self) -> Result<Vec<Option<(Self::Reader, Self::Writer)>>>;
+}
+
+// connections over TCP, IPv4 or IPv6, as described by a NetworkConfig.
+pub struct TcpTransport {
+    config:     NetworkConfig,
+    index:      u64,
+}
+
+impl TcpTransport {
+    pub fn new(addresses: Vec<String>, index: u64) -> TcpTransport {
+        TcpTransport::from_config(NetworkConfig::new(addresses), index)
+    }
+    pub fn from_config(config: NetworkConfig, index: u64) -> TcpTransport {
+        TcpTransport { config: config, index: index }
+    }
+}
+
+impl Transport for TcpTransport {
+    type Reader = TcpStream;
+    type Writer = TcpStream;
+
+    fn index(&self) -> u64 { self.index }
+    fn processes(&self) -> u64 { self.config.addresses.len() as u64 }
+
+    fn connect(self) -> Result<Vec<Option<(TcpStream, TcpStream)>>> {
+        let my_index = self.index;
+        let processes = self.processes();
+        let retry = self.config.retry;
+
+        let bind = self.config.bind_address(my_index);
+        let listener = try!(TcpListener::bind(&bind[..]));
+        let addresses = self.config.addresses;
+
+        let done = Arc::new(AtomicBool::new(false));
+        start_alarm(retry.deadline_ms, done.clone(), move || TcpStream::connect(&bind[..]));
+
+        let start_task = Future::spawn(move || start_connections(my_index, retry, |index, timeout_ms| {
+            let address = addresses[index as usize].clone();
+            connect_within(timeout_ms, move || TcpStream::connect(&address[..]))
+        }));
+        let await_task = Future::spawn(move || await_connections(my_index, processes, || listener.accept().map(|x| x.0)));
+
+        let streams = try!(assemble(start_task.into_inner(), await_task.into_inner()));
+        done.store(true, Ordering::SeqCst);
+        let mut results = Vec::new();
+        for stream in streams.into_iter() {
+            results.push(match stream {
+                Some(stream) => { let reader = try!(stream.try_clone()); Some((reader, stream)) },
+                None         => None,
+            });
+        }
+        Ok(results)
+    }
+}
+
+// connections over Unix domain sockets, one socket path per process, for processes sharing a host.
+pub struct UnixTransport {
+    paths:  Vec<String>,
+    index:  u64,
+    retry:  Retry,
+}
+
+impl UnixTransport {
+    pub fn new(paths: Vec<String>, index: u64) -> UnixTransport {
+        UnixTransport { paths: paths, index: index, retry: Default::default() }
+    }
+
+    pub fn retry(mut self, retry: Retry) -> UnixTransport { self.retry = retry; self }
+}
+
+impl Transport for UnixTransport {
+    type Reader = UnixStream;
+    type Writer = UnixStream;
+
+    fn index(&self) -> u64 { self.index }
+    fn processes(&self) -> u64 { self.paths.len() as u64 }
+
+    fn connect(self) -> Result<Vec<Option<(UnixStream, UnixStream)>>> {
+        let my_index = self.index;
+        let processes = self.processes();
+
+        // a socket file left behind by an earlier run would prevent binding. ours is removed once connect returns,
+        // as by then every peer has connected through it, or none will.
+        fs::remove_file(&self.paths[my_index as usize][..]).ok();
+        let listener = try!(UnixListener::bind(&self.paths[my_index as usize][..]));
+        let _unlink = Unlink(self.paths[my_index as usize].clone());
+        let paths = self.paths;
+        let retry = self.retry;
+
+        let done = Arc::new(AtomicBool::new(false));
+        let own_path = paths[my_index as usize].clone();
+        start_alarm(retry.deadline_ms, done.clone(), move || UnixStream::connect(&own_path[..]));
+
+        let start_task = Future::spawn(move || start_connections(my_index, retry, |index, _| UnixStream::connect(&paths[index as usize][..])));
+        let await_task = Future::spawn(move || await_connections(my_index, processes, || listener.accept()));
+
+        let streams = try!(assemble(start_task.into_inner(), await_task.into_inner()));
+        done.store(true, Ordering::SeqCst);
+        let mut results = Vec::new();
+        for stream in streams.into_iter() {
+            results.push(match stream {
+                Some(stream) => { let reader = try!(stream.try_clone()); Some((reader, stream)) },
+                None         => None,
+            });
+        }
+        Ok(results)
+    }
+}
+
+// removes a file when dropped.
+struct Unlink(String);
+impl Drop for Unlink {
+    fn drop(&mut self) { fs::remove_file(&self.0).ok(); }
+}
+
+// connections over anonymous pipes, created before forking the processes that will use them.
+// each child should use the transport at its own index, and drop the others.
+pub struct PipeTransport {
+    pipes:  Vec<Option<(RawFd, RawFd)>>,    // (read, write) descriptors for each process
+    index:  u64,
+}
+
+impl PipeTransport {
+    // creates two pipes between each pair of processes, and the transport for each process.
+    pub fn new_vector(processes: u64) -> Result<Vec<PipeTransport>> {
+        let mut results: Vec<_> = (0..processes).map(|index| PipeTransport {
+            pipes: (0..processes).map(|_| None).collect(),
+            index: index,
+        }).collect();
+
+        for source in (0..processes as usize) {
+            for target in (source + 1 .. processes as usize) {
+                let (s_to_t_read, s_to_t_write) = try!(pipe());
+                let (t_to_s_read, t_to_s_write) = try!(pipe());
+                results[source].pipes[target] = Some((t_to_s_read, s_to_t_write));
+                results[target].pipes[source] = Some((s_to_t_read, t_to_s_write));
+            }
+        }
+
+        Ok(results)
+    }
+
+    // describes the descriptors of each of `transports`, for processes that inherit them (see from_argument).
+    pub fn to_argument(transports: &[PipeTransport]) -> String {
+        let rows: Vec<String> = transports.iter().map(|transport| {
+            let entries: Vec<String> = transport.pipes.iter().map(|pipe| match *pipe {
+                Some((read, write)) => format!("{}:{}", read, write),
+                None                => "-".to_string(),
+            }).collect();
+            entries.connect(",")
+        }).collect();
+        rows.connect("/")
+    }
+
+    // the transport at `index` of those `argument` describes, in a process that inherited their descriptors. the
+    // descriptors of the other processes are closed, so that each process observes end-of-stream when a peer exits.
+    // a malformed argument is rejected whole, before any descriptor is owned (and so closed): those it names may be
+    // nothing of ours, or even our standard streams.
+    pub fn from_argument(argument: &str, index: u64) -> Result<PipeTransport> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid pipe descriptors: {}", argument));
+        let mut rows = Vec::new();
+        for row in argument.split('/') {
+            let mut pipes = Vec::new();
+            for entry in row.split(',') {
+                if entry == "-" { pipes.push(None); }
+                else {
+                    let fds: Vec<&str> = entry.split(':').collect();
+                    if fds.len() != 2 { return Err(invalid()); }
+                    let read = try!(fds[0].parse::<RawFd>().map_err(|_| invalid()));
+                    let write = try!(fds[1].parse::<RawFd>().map_err(|_| invalid()));
+                    if read < 0 || write < 0 { return Err(invalid()); }
+                    pipes.push(Some((read, write)));
+                }
+            }
+            rows.push(pipes);
+        }
+
+        // a process has no pipe to itself, and one to every other process
+        let processes = rows.len();
+        if index as usize >= processes || rows.iter().any(|pipes| pipes.len() != processes) {
+            return Err(invalid());
+        }
+        for (process, pipes) in rows.iter().enumerate() {
+            if pipes.iter().enumerate().any(|(peer, pipe)| pipe.is_some() == (peer == process)) { return Err(invalid()); }
+        }
+
+        let mut transports: Vec<PipeTransport> = rows.into_iter().enumerate().map(|(process, pipes)| {
+            PipeTransport { pipes: pipes, index: process as u64 }
+        }).collect();
+        Ok(transports.swap_remove(index as usize))
+    }
+}
+
+impl Drop for PipeTransport {
+    // descriptors not claimed by connect() are closed, so that peers observe end-of-stream.
+    fn drop(&mut self) {
+        for pipe in self.pipes.iter_mut() {
+            if let Some((read, write)) = pipe.take() {
+                unsafe { libc::close(read); libc::close(write); }
+            }
+        }
+    }
+}
+
+impl Transport for PipeTransport {
+    type Reader = PipeEnd;
+    type Writer = PipeEnd;
+
+    fn index(&self) -> u64 { self.index }
+    fn processes(&self) -> u64 { self.pipes.len() as u64 }
+
+    fn connect(mut self) -> Result<Vec<Option<(PipeEnd, PipeEnd)>>> {
+        Ok(self.pipes.iter_mut().map(|pipe| pipe.take().map(|(read, write)| (PipeEnd { fd: read }, PipeEnd { fd: write }))).collect())
+    }
+}
+
+// one end of an anonymous pipe, closed when dropped.
+pub struct PipeEnd {
+    fd: RawFd,
+}
+
+impl Read for PipeEnd {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len() as libc::size_t) };
+        if read < 0 { Err(Error::last_os_error()) } else { Ok(read as usize) }
+    }
+}
+
+impl Write for PipeEnd {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = unsafe { libc::write(self.fd, buf.as_ptr() as *const libc::c_void, buf.len() as libc::size_t) };
+        if written < 0 { Err(Error::last_os_error()) } else { Ok(written as usize) }
+    }
+    fn flush(&mut self) -> Result<()> { Ok(()) }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) { unsafe { libc::close(self.fd); } }
+}
+
+fn pipe() -> Result<(RawFd, RawFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 { Err(Error::last_os_error()) }
+    else { Ok((fds[0], fds[1])) }
+}
+
+// combines connections to lower and higher processes into one vector indexed by process,
+// or the errors from failing to establish either.
+fn assemble<S>(lower: Result<Vec<Option<S>>>, higher: Result<Vec<Option<S>>>) -> Result<Vec<Option<S>>> {
+    match (lower, higher) {
+        (Ok(mut lower), Ok(mut higher)) => {
+            lower.push(None);
+            lower.append(&mut higher);
+            Ok(lower)
+        },
+        (Err(lower), Err(higher)) => Err(Error::new(ErrorKind::Other, format!("{}; {}", lower, higher))),
+        (Err(error), _) | (_, Err(error)) => Err(error),
+    }
+}
+
+// after deadline_ms, unless done, wakes await_connections by connecting to our own listener as WAKEUP.
+fn start_alarm<S: Write, F: Fn()->Result<S>+Send+'static>(deadline_ms: u64, done: Arc<AtomicBool>, connect: F) {
+    thread::spawn(move || {
+        sleep_ms(min(deadline_ms, !0u32 as u64) as u32);
+        if !done.load(Ordering::SeqCst) {
+            if let Ok(mut stream) = connect() { stream.write_u64::<LittleEndian>(WAKEUP).ok(); }
+        }
+    });
+}
+
+// runs connect on another thread, giving up on it after timeout_ms. TcpStream::connect has no timeout of its own, and
+// an address that drops packets can hold it for minutes; a connection given up on is closed should it complete.
+fn connect_within<S: Send+'static, F: FnOnce()->Result<S>+Send+'static>(timeout_ms: u64, connect: F) -> Result<S> {
+    let shared = Arc::new((Mutex::new(None), Condvar::new()));
+    let remote = shared.clone();
+    thread::spawn(move || {
+        let result = connect();
+        let &(ref lock, ref condvar) = &*remote;
+        *lock.lock().ok().expect("mutex error?") = Some(result);
+        condvar.notify_one();
+    });
+
+    let start = time::precise_time_ns();
+    let &(ref lock, ref condvar) = &*shared;
+    let mut result = lock.lock().ok().expect("mutex error?");
+    while result.is_none() {
+        let elapsed = (time::precise_time_ns() - start) / 1_000_000;
+        if elapsed >= timeout_ms {
+            return Err(Error::new(ErrorKind::TimedOut, format!("no connection within {}ms", timeout_ms)));
+        }
+        result = condvar.wait_timeout_ms(result, min(timeout_ms - elapsed, !0u32 as u64) as u32).ok().expect("mutex error?").0;
+    }
+    result.take().unwrap()
+}
+
+// result contains connections [0, my_index - 1], or an error listing those not made by the deadline.
+// connections are attempted in rounds, with exponentially growing delays between rounds. connect is passed the time
+// remaining until the deadline, which it should not block for longer than.
+fn start_connections<S: Write, F: Fn(u64, u64)->Result<S>>(my_index: u64, retry: Retry, connect: F) -> Result<Vec<Option<S>>> {
+    let start = time::precise_time_ns();
+    let mut results: Vec<_> = (0..my_index).map(|_| None).collect();
+    let mut delay = retry.initial_ms;
+
+    loop {
+        for index in (0..my_index) {
+            if results[index as usize].is_none() {
+                let remaining = retry.deadline_ms.saturating_sub((time::precise_time_ns() - start) / 1_000_000);
+                let attempt = connect(index, remaining).and_then(|mut stream| {
+                    try!(stream.write_u64::<LittleEndian>(my_index));
+                    Ok(stream)
+                });
+                match attempt {
+                    Ok(stream) => {
+                        results[index as usize] = Some(stream);
+                        println!("worker {}:\tconnection to worker {}", my_index, index);
+                    },
+                    Err(error) => {
+                        println!("worker {}:\terror connecting to worker {}: {}; retrying", my_index, index, error);
+                    },
+                }
+            }
+        }
+
+        let missing: Vec<u64> = (0..my_index).filter(|&index| results[index as usize].is_none()).collect();
+        if missing.len() == 0 { return Ok(results); }
+
+        let elapsed = (time::precise_time_ns() - start) / 1_000_000;
+        if elapsed >= retry.deadline_ms {
+            return Err(Error::new(ErrorKind::Other, format!("process {}: unable to connect to processes {:?} within {}ms",
+                                                            my_index, missing, retry.deadline_ms)));
+        }
+
+        sleep_ms(min(delay as u64, retry.deadline_ms - elapsed) as u32);
+        delay = min(delay.saturating_mul(2), retry.maximum_ms);
+    }
+}
+
+// result contains connections [my_index + 1, processes - 1], or an error listing those not received when the
+// deadline alarm goes off.
+fn await_connections<S: Read, F: FnMut()->Result<S>>(my_index: u64, processes: u64, mut accept: F) -> Result<Vec<Option<S>>> {
+    let mut results: Vec<_> = (0..(processes - my_index - 1)).map(|_| None).collect();
+
+    while results.iter().any(|result| result.is_none()) {
+        let mut stream = try!(accept());
+        let identifier = try!(stream.read_u64::<LittleEndian>());
+        if identifier == WAKEUP {
+            let missing: Vec<u64> = (my_index + 1 .. processes).filter(|&index| results[(index - my_index - 1) as usize].is_none()).collect();
+            return Err(Error::new(ErrorKind::Other, format!("process {}: no connection from processes {:?} by the deadline", my_index, missing)));
+        }
+        if identifier <= my_index || identifier >= processes || results[(identifier - my_index - 1) as usize].is_some() {
+            return Err(Error::new(ErrorKind::Other, format!("process {}: connection from unexpected process {}", my_index, identifier)));
+        }
+        results[(identifier - my_index - 1) as usize] = Some(stream);
+        println!("worker {}:\tconnection from worker {}", my_index, identifier);
+    }
+
+    return Ok(results);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write, Error, ErrorKind};
+    use std::thread::sleep_ms;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use time;
+
+    use networking::config::Retry;
+    use super::{Transport, PipeTransport, PipeEnd, WAKEUP, start_connections, connect_within, await_connections};
+
+    #[test]
+    fn unreachable_processes_are_listed() {
+        let retry = Retry { initial_ms: 1, maximum_ms: 4, deadline_ms: 20 };
+        let result = start_connections(3, retry, |index, _| {
+            if index == 1 { Ok(Vec::<u8>::new()) } else { Err(Error::new(ErrorKind::Other, "refused")) }
+        });
+        let error = format!("{}", result.err().unwrap());
+        assert!(error.contains("[0, 2]"));
+    }
+
+    #[test]
+    fn connections_give_up_at_the_deadline() {
+        use std::sync::mpsc::channel;
+
+        // a connection attempt that never completes, as to an address that drops packets
+        let (_hold, never) = channel::<()>();
+        let start = time::precise_time_ns();
+        assert!(connect_within(20, move || { never.recv().ok(); Ok(()) }).is_err());
+        assert!((time::precise_time_ns() - start) / 1_000_000 < 1000);
+        assert_eq!(connect_within(1000, || Ok(5)).unwrap(), 5);
+
+        let retry = Retry { initial_ms: 1, maximum_ms: 4, deadline_ms: 20 };
+        let start = time::precise_time_ns();
+        let result = start_connections(1, retry, |_, timeout_ms| connect_within(timeout_ms, || { sleep_ms(60000); Ok(Vec::<u8>::new()) }));
+        assert!(result.is_err());
+        assert!((time::precise_time_ns() - start) / 1_000_000 < 1000);
+    }
+
+    #[test]
+    fn silent_processes_are_listed_at_the_deadline() {
+        let mut identifiers = vec![3u64, WAKEUP].into_iter();
+        let result = await_connections(1, 5, || {
+            let mut bytes = Vec::new();
+            bytes.write_u64::<LittleEndian>(identifiers.next().unwrap()).unwrap();
+            Ok(::std::io::Cursor::new(bytes))
+        });
+        let error = format!("{}", result.err().unwrap());
+        assert!(error.contains("[2, 4]"));
+    }
+
+    #[test]
+    fn unexpected_processes_are_rejected() {
+        let result = await_connections(1, 3, || Ok(&[7u8, 0, 0, 0, 0, 0, 0, 0][..]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn pipes_connect_processes() {
+        let mut transports = PipeTransport::new_vector(2).unwrap();
+        let mut second = transports.pop().unwrap().connect().unwrap();
+        let mut first = transports.pop().unwrap().connect().unwrap();
+
+        first[1].as_mut().unwrap().1.write_all(b"hello").unwrap();
+        let mut buffer = [0u8; 16];
+        assert_eq!(second[0].as_mut().unwrap().0.read(&mut buffer).unwrap(), 5);
+        assert_eq!(&buffer[..5], b"hello");
+
+        // no other copies of the descriptors remain, so a departed peer is seen as end-of-stream
+        drop(first);
+        assert_eq!(second[0].as_mut().unwrap().0.read(&mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn pipe_arguments_describe_transports() {
+        let transports = PipeTransport::new_vector(3).unwrap();
+        let argument = PipeTransport::to_argument(&transports[..]);
+        let expected = transports[1].pipes.clone();
+
+        // from_argument takes ownership of the descriptors, as a process inheriting them would
+        for mut transport in transports.into_iter() { for pipe in transport.pipes.iter_mut() { pipe.take(); } }
+        let transport = PipeTransport::from_argument(&argument[..], 1).unwrap();
+        assert_eq!(transport.index, 1);
+        assert_eq!(transport.pipes, expected);
+
+        // malformed descriptions are rejected
+        assert!(PipeTransport::from_argument("", 0).is_err());
+        assert!(PipeTransport::from_argument("-,90001:90002/90003:90004", 0).is_err());
+        assert!(PipeTransport::from_argument("-,90001:90002/90003:90004,-", 2).is_err());
+        assert!(PipeTransport::from_argument("-,90001:x/90003:90004,-", 0).is_err());
+        assert!(PipeTransport::from_argument("-,90001:-90002/90003:90004,-", 0).is_err());
+        assert!(PipeTransport::from_argument("90001:90002,-/-,90003:90004", 0).is_err());
+
+        // without closing the descriptors they name, which are not the transport's to close
+        let transports = PipeTransport::new_vector(2).unwrap();
+        let argument = format!("{}/-,-", PipeTransport::to_argument(&transports[..]));
+        let (read, write) = transports[0].pipes[1].unwrap();
+        let (peer_read, peer_write) = transports[1].pipes[0].unwrap();
+        for mut transport in transports.into_iter() { for pipe in transport.pipes.iter_mut() { pipe.take(); } }
+        assert!(PipeTransport::from_argument(&argument[..], 0).is_err());
+
+        let mut buffer = [0u8; 1];
+        let (_read, mut write, mut peer_read, _peer_write) = (PipeEnd { fd: read }, PipeEnd { fd: write }, PipeEnd { fd: peer_read }, PipeEnd { fd: peer_write });
+        write.write_all(&[7]).unwrap();
+        assert_eq!(peer_read.read(&mut buffer).unwrap(), 1);
+        assert_eq!(buffer, [7]);
+    }
+}
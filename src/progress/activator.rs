@@ -0,0 +1,23 @@
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+
+// Lets a scope ask its subgraph to schedule it in the next step. scopes scheduled on activation are otherwise only
+// scheduled when messages wait at their inputs or their input frontiers change, so a scope with work left after a
+// step (e.g. input it chose not to process yet, or input arriving from outside the dataflow) should ask.
+#[derive(Clone)]
+pub struct Activator {
+    index:      u64,                    // the scope's index in its subgraph
+    requests:   Rc<RefCell<Vec<u64>>>,  // scopes of the subgraph that have asked to be scheduled
+    activity:   Rc<Cell<u64>>,          // the dataflow's activity, so that its worker does not park
+}
+
+impl Activator {
+    pub fn new(index: u64, requests: Rc<RefCell<Vec<u64>>>, activity: Rc<Cell<u64>>) -> Activator {
+        Activator { index: index, requests: requests, activity: activity }
+    }
+
+    pub fn activate(&self) {
+        self.requests.borrow_mut().push(self.index);
+        self.activity.set(self.activity.get() + 1);
+    }
+}
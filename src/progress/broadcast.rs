@@ -1,34 +1,357 @@
-use progress::Timestamp;
-use communication::{Communicator, Pushable, Pullable};
+use std::rc::Rc;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use progress::{Timestamp, CountMap};
+use progress::frontier::MutableAntichain;
+use communication::{Communicator, Pushable, Pullable, PushHandle, PullHandle};
 use columnar::Columnar;
 
 pub type ProgressVec<T> = Vec<(u64, u64, T, i64)>;  // (child_scope, [in/out]port, timestamp, delta)
 
+// How a dataflow's workers exchange progress updates.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgressProtocol {
+    Broadcast,          // each worker sends its updates to every worker
+    Aggregated(u64),    // through a leader for each group of this many consecutive workers
+    Centralized,        // through worker zero, which tracks the updates of all workers and sends changes in frontiers
+}
+
+// Progcaster exchanges progress updates among all workers, either directly, through leaders, or through a coordinator.
+// with leaders, each worker sends its updates to the leader of its group, which merges the updates of its group, sends
+// them to the other leaders, and relays merged updates from its own and other groups to the workers of its group.
+//
+// with a coordinator, each worker sends its updates to worker zero, which applies them to the counts of all workers
+// and sends on only changes to frontiers: as what each worker holds need only have the same frontier as those counts,
+// not the same counts, the coordinator keeps each location at one count per element of its frontier. it starts from
+// the scopes' initial capabilities (see take_capabilities), which every worker takes alike for the same dataflow.
+//
+// updates from each worker still arrive in the order sent, but only the leaders and coordinator forward them, so they
+// must keep stepping for as long as the other workers do.
 pub struct Progcaster<T:Timestamp> {
-    senders:    Vec<Box<Pushable<(ProgressVec<T>, ProgressVec<T>)>>>,
-    receiver:   Box<Pullable<(ProgressVec<T>, ProgressVec<T>)>>,
+    senders:    Vec<PushHandle<(ProgressVec<T>, ProgressVec<T>)>>,
+    receiver:   PullHandle<(ProgressVec<T>, ProgressVec<T>)>,
+    received:   Vec<(ProgressVec<T>, ProgressVec<T>)>,  // scratch space for draining receiver
+    groups:     Option<Aggregator<T>>,
+    coordinator:Option<Coordinator<T>>,
+    index:      u64,
+    protocol:   ProgressProtocol,
+    activity:   Rc<Cell<u64>>,  // batches of updates sent and received, shared with nested scopes and activators
+}
+
+// a worker's channels to its leader, and a leader's channels to the other leaders.
+struct Aggregator<T:Timestamp> {
+    size:       u64,                                                    // workers per group
+    local:      Vec<PushHandle<(ProgressVec<T>, ProgressVec<T>)>>,      // updates to the leader of a worker's group
+    gathered:   PullHandle<(ProgressVec<T>, ProgressVec<T>)>,           // updates of this leader's group
+    remote:     Vec<PushHandle<(ProgressVec<T>, ProgressVec<T>)>>,      // merged updates to the leaders of other groups
+    exchanged:  PullHandle<(ProgressVec<T>, ProgressVec<T>)>,           // merged updates of other groups
+    messages:   CountMap<(u64, u64, T)>,                                // for merging updates
+    internal:   CountMap<(u64, u64, T)>,
+}
+
+// a worker's channels to the coordinator, and the coordinator's view of the counts at each location.
+struct Coordinator<T:Timestamp> {
+    local:      Vec<PushHandle<(ProgressVec<T>, ProgressVec<T>)>>,      // updates to the coordinator
+    gathered:   PullHandle<(ProgressVec<T>, ProgressVec<T>)>,           // updates of all workers
+    messages:   HashMap<(u64, u64), Tracked<T>>,                        // by (scope, input)
+    internal:   HashMap<(u64, u64), Tracked<T>>,                        // by (scope, output)
+    changed:    (Vec<(u64, u64)>, Vec<(u64, u64)>),                     // locations updated since changes were sent
+}
+
+// the counts of all workers' updates at a location, and the counts workers were last sent for it.
+struct Tracked<T:Timestamp> {
+    counts:     MutableAntichain<T>,
+    presented:  CountMap<T>,
 }
 
 impl<T:Timestamp+Send+Columnar> Progcaster<T> {
+    // uses the communicator's grouping of workers, if any, and otherwise broadcasts.
     pub fn new<C: Communicator>(communicator: &mut C) -> Progcaster<T> {
-        let (senders, receiver) = communicator.new_channel();
-        Progcaster { senders: senders, receiver: receiver }
+        let protocol = match communicator.progress_groups() {
+            Some(size) => ProgressProtocol::Aggregated(size),
+            None       => ProgressProtocol::Broadcast,
+        };
+        Progcaster::with_protocol(communicator, protocol)
     }
-    pub fn send_and_recv(&mut self, messages: &mut ProgressVec<T>, internal: &mut ProgressVec<T>) -> () {
-        if self.senders.len() > 1 {  // if the length is one, just return the updates...
-            if messages.len() > 0 || internal.len() > 0 {
-                for sender in self.senders.iter_mut() {
-                    sender.push((messages.clone(), internal.clone()));
+
+    pub fn with_protocol<C: Communicator>(communicator: &mut C, protocol: ProgressProtocol) -> Progcaster<T> {
+        let (senders, receiver) = communicator.new_priority_channel("progress");
+        let groups = match protocol {
+            ProgressProtocol::Aggregated(size) => Some(size),
+            _                                  => None,
+        };
+        let groups = groups.map(|size| {
+            let (local, gathered) = communicator.new_priority_channel("progress (local)");
+            let (remote, exchanged) = communicator.new_priority_channel("progress (remote)");
+            Aggregator {
+                size:       size,
+                local:      local,
+                gathered:   gathered,
+                remote:     remote,
+                exchanged:  exchanged,
+                messages:   CountMap::new(),
+                internal:   CountMap::new(),
+            }
+        });
+        let coordinator = if protocol == ProgressProtocol::Centralized {
+            let (local, gathered) = communicator.new_priority_channel("progress (coordinator)");
+            Some(Coordinator {
+                local:      local,
+                gathered:   gathered,
+                messages:   HashMap::new(),
+                internal:   HashMap::new(),
+                changed:    (Vec::new(), Vec::new()),
+            })
+        }
+        else { None };
+        Progcaster {
+            senders:    senders,
+            receiver:   receiver,
+            received:   Vec::new(),
+            groups:     groups,
+            coordinator:coordinator,
+            index:      communicator.index(),
+            protocol:   protocol,
+            activity:   Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub fn protocol(&self) -> ProgressProtocol { self.protocol }
+
+    // the capabilities the scopes of the dataflow start with, as (scope, output, time, count); the coordinator counts
+    // updates from these, and first sends workers the change from them to their frontiers. other workers ignore them.
+    pub fn take_capabilities(&mut self, capabilities: &ProgressVec<T>) {
+        if self.index == 0 {
+            if let Some(ref mut coordinator) = self.coordinator {
+                for &(scope, output, time, count) in capabilities.iter() {
+                    coordinator.track_internal(&(scope, output, time, count));
+                    coordinator.internal.get_mut(&(scope, output)).unwrap().presented.update(&time, count);
                 }
+            }
+        }
+    }
+
+    // a worker whose dataflow exchanges no progress updates in a step has nothing to do until a message arrives, so
+    // the progcasters of a dataflow count their traffic together, to be checked at its root.
+    pub fn activity(&self) -> u64 { self.activity.get() }
+    pub fn activity_counter(&self) -> Rc<Cell<u64>> { self.activity.clone() }
+    pub fn share_activity<S:Timestamp>(&mut self, other: &Progcaster<S>) { self.activity = other.activity.clone(); }
 
-                messages.clear();
-                internal.clear();
+    pub fn send_and_recv(&mut self, messages: &mut ProgressVec<T>, internal: &mut ProgressVec<T>) -> () {
+        if messages.len() > 0 || internal.len() > 0 { self.activity.set(self.activity.get() + 1); }
+        if self.senders.len() > 1 {  // if the length is one, just return the updates...
+            match (&mut self.groups, &mut self.coordinator) {
+                (&mut None, &mut None) => {
+                    if messages.len() > 0 || internal.len() > 0 {
+                        for sender in self.senders.iter_mut() {
+                            sender.push((messages.clone(), internal.clone()));
+                        }
+                    }
+                },
+                (_, &mut Some(ref mut coordinator)) => {
+                    if messages.len() > 0 || internal.len() > 0 {
+                        coordinator.local[0].push((messages.clone(), internal.clone()));
+                    }
+                    if self.index == 0 {
+                        coordinator.coordinate(&mut self.senders, &mut self.received);
+                    }
+                },
+                (&mut Some(ref mut groups), _) => {
+                    let leader = self.index - self.index % groups.size;
+                    if messages.len() > 0 || internal.len() > 0 {
+                        groups.local[leader as usize].push((messages.clone(), internal.clone()));
+                    }
+                    if self.index == leader {
+                        groups.relay(leader, &mut self.senders, &mut self.received);
+                    }
+                },
             }
 
-            while let Some((mut recv_messages, mut recv_internal)) = self.receiver.pull() {
+            messages.clear();
+            internal.clear();
+
+            self.receiver.pull_all(&mut self.received);
+            if self.received.len() > 0 { self.activity.set(self.activity.get() + 1); }
+            for (mut recv_messages, mut recv_internal) in self.received.drain() {
                 messages.append(&mut recv_messages);
                 internal.append(&mut recv_internal);
             }
         }
     }
 }
+
+impl<T:Timestamp+Send+Columnar> Aggregator<T> {
+    // merges the updates of the group led by leader and sends them to the other leaders, and sends both them and
+    // the merged updates of other groups to the workers of the group.
+    fn relay(&mut self, leader: u64, senders: &mut Vec<PushHandle<(ProgressVec<T>, ProgressVec<T>)>>,
+                                     received: &mut Vec<(ProgressVec<T>, ProgressVec<T>)>) {
+        let peers = senders.len() as u64;
+        let members = leader..(if leader + self.size < peers { leader + self.size } else { peers });
+
+        self.gathered.pull_all(received);
+        for (recv_messages, recv_internal) in received.drain() {
+            for (a, b, c, d) in recv_messages.into_iter() { self.messages.update(&(a, b, c), d); }
+            for (a, b, c, d) in recv_internal.into_iter() { self.internal.update(&(a, b, c), d); }
+        }
+
+        // updates that cancel out within the group need not leave the leader
+        if self.messages.len() > 0 || self.internal.len() > 0 {
+            let mut merged_messages = Vec::new();
+            let mut merged_internal = Vec::new();
+            while let Some(((a, b, c), d)) = self.messages.pop() { merged_messages.push((a, b, c, d)); }
+            while let Some(((a, b, c), d)) = self.internal.pop() { merged_internal.push((a, b, c, d)); }
+
+            let size = self.size;
+            for other in (0..peers).filter(|&other| other % size == 0 && other != leader) {
+                self.remote[other as usize].push((merged_messages.clone(), merged_internal.clone()));
+            }
+            received.push((merged_messages, merged_internal));
+        }
+
+        self.exchanged.pull_all(received);
+        for (recv_messages, recv_internal) in received.drain() {
+            for member in members.clone() {
+                senders[member as usize].push((recv_messages.clone(), recv_internal.clone()));
+            }
+        }
+    }
+}
+
+impl<T:Timestamp+Send+Columnar> Coordinator<T> {
+    // applies the updates of all workers, and sends every worker the changes in frontiers they bring about.
+    fn coordinate(&mut self, senders: &mut Vec<PushHandle<(ProgressVec<T>, ProgressVec<T>)>>,
+                             received: &mut Vec<(ProgressVec<T>, ProgressVec<T>)>) {
+        // each worker's updates are applied whole, so that a message is never counted out before the capability
+        // it was sent at, or the message in reply to which it was sent.
+        self.gathered.pull_all(received);
+        for (recv_messages, recv_internal) in received.drain() {
+            for update in recv_messages.iter() { self.track_messages(update); }
+            for update in recv_internal.iter() { self.track_internal(update); }
+        }
+
+        let mut changes = (Vec::new(), Vec::new());
+        for location in self.changed.0.drain() { present(location, self.messages.get_mut(&location).unwrap(), &mut changes.0); }
+        for location in self.changed.1.drain() { present(location, self.internal.get_mut(&location).unwrap(), &mut changes.1); }
+
+        if changes.0.len() > 0 || changes.1.len() > 0 {
+            for sender in senders.iter_mut() { sender.push(changes.clone()); }
+        }
+    }
+
+    fn track_messages(&mut self, &(scope, input, time, delta): &(u64, u64, T, i64)) {
+        track(&mut self.messages, &mut self.changed.0, (scope, input), time, delta);
+    }
+    fn track_internal(&mut self, &(scope, output, time, delta): &(u64, u64, T, i64)) {
+        track(&mut self.internal, &mut self.changed.1, (scope, output), time, delta);
+    }
+}
+
+fn track<T:Timestamp>(locations: &mut HashMap<(u64, u64), Tracked<T>>, changed: &mut Vec<(u64, u64)>, location: (u64, u64), time: T, delta: i64) {
+    if !locations.contains_key(&location) {
+        locations.insert(location, Tracked { counts: MutableAntichain::new(), presented: CountMap::new() });
+    }
+    locations.get_mut(&location).unwrap().counts.update_and(&time, delta, |_, _| { });
+    if !changed.contains(&location) { changed.push(location); }
+}
+
+// sends the change from what workers were last sent for a location to one count for each element of its frontier.
+fn present<T:Timestamp>((scope, port): (u64, u64), tracked: &mut Tracked<T>, changes: &mut ProgressVec<T>) {
+    let mut change = CountMap::new();
+    for time in tracked.counts.elements.iter() { change.update(time, 1); }
+    for &(ref time, count) in tracked.presented.elements().iter() { change.update(time, -count); }
+    while let Some((time, delta)) = change.pop() {
+        tracked.presented.update(&time, delta);
+        changes.push((scope, port, time, delta));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use columnar::Columnar;
+    use communication::{Communicator, PushHandle, PullHandle};
+    use progress::CountMap;
+
+    use super::{Progcaster, ProgressProtocol};
+
+    #[test]
+    fn grouped_progress_reaches_every_worker() {
+        use communication::ProcessCommunicator;
+        use std::any::Any;
+
+        // a ProcessCommunicator whose workers are grouped as if spread over processes.
+        struct Grouped(ProcessCommunicator, u64);
+        impl Communicator for Grouped {
+            fn index(&self) -> u64 { self.0.index() }
+            fn peers(&self) -> u64 { self.0.peers() }
+            fn new_channel<D:Send+Columnar+Any>(&mut self, name: &str) -> (Vec<PushHandle<D>>, PullHandle<D>) { self.0.new_channel(name) }
+            fn progress_groups(&self) -> Option<u64> { Some(self.1) }
+        }
+
+        // six workers in groups of four and two, stepped in turn.
+        let mut communicators: Vec<Grouped> = ProcessCommunicator::new_vector(6).into_iter().map(|c| Grouped(c, 4)).collect();
+        let mut progcasters: Vec<Progcaster<u64>> = communicators.iter_mut().map(|c| Progcaster::new(c)).collect();
+        let mut totals = vec![(CountMap::new(), CountMap::new()); 6];
+
+        for round in (0..5) {
+            for index in (0..6) {
+                // in the first three rounds, each worker retires its message update from the previous round, so that
+                // updates cancel within groups; the remaining rounds only deliver updates still in flight.
+                let (mut messages, mut internal) = (Vec::new(), Vec::new());
+                if round < 3 {
+                    messages.push((0, 0, round, 1));
+                    if round > 0 { messages.push((0, 0, round - 1, -1)); }
+                    internal.push((index, 0, round, 1));
+                }
+                progcasters[index as usize].send_and_recv(&mut messages, &mut internal);
+                let (ref mut total_messages, ref mut total_internal) = totals[index as usize];
+                for (a, b, c, d) in messages.into_iter() { total_messages.update(&(a, b, c), d); }
+                for (a, b, c, d) in internal.into_iter() { total_internal.update(&(a, b, c), d); }
+            }
+        }
+
+        // every worker sees the sum of all updates: six messages at round two, and each worker's internal updates.
+        let mut expected_internal = Vec::new();
+        for index in (0..6) { for round in (0..3) { expected_internal.push(((index, 0, round), 1)); } }
+        expected_internal.sort();
+        for &(ref total_messages, ref total_internal) in totals.iter() {
+            assert_eq!(total_messages.elements(), &vec![((0, 0, 2), 6)]);
+            let mut elements = total_internal.elements().clone();
+            elements.sort();
+            assert_eq!(elements, expected_internal);
+        }
+    }
+
+    #[test]
+    fn coordinator_sends_frontier_changes() {
+        use communication::ProcessCommunicator;
+
+        let mut communicators = ProcessCommunicator::new_vector(3);
+        let mut progcasters: Vec<Progcaster<u64>> = communicators.iter_mut().map(|c| Progcaster::with_protocol(c, ProgressProtocol::Centralized)).collect();
+        for progcaster in progcasters.iter_mut() { progcaster.take_capabilities(&vec![(0, 0, 0, 3)]); }
+
+        // each worker steps its capability forward, workers one and two before the coordinator has.
+        progcasters[1].send_and_recv(&mut Vec::new(), &mut vec![(0, 0, 0, -1), (0, 0, 1, 1)]);
+        progcasters[2].send_and_recv(&mut Vec::new(), &mut vec![(0, 0, 0, -1), (0, 0, 1, 1)]);
+
+        // every worker hears of the frontier moving, from three counts at zero to one count at one.
+        for index in (0..3) {
+            let (mut messages, mut internal) = (Vec::new(), Vec::new());
+            if index == 0 { internal = vec![(0, 0, 0, -1), (0, 0, 1, 1)]; }
+            progcasters[index].send_and_recv(&mut messages, &mut internal);
+            internal.sort();
+            assert_eq!(messages, vec![]);
+            assert_eq!(internal, vec![(0, 0, 0, -3), (0, 0, 1, 1)]);
+        }
+
+        // a message sent and received, and a capability held beside another at the frontier, move no frontier.
+        progcasters[1].send_and_recv(&mut vec![(1, 0, 5, 1)], &mut vec![(0, 0, 1, 1)]);
+        progcasters[2].send_and_recv(&mut vec![(1, 0, 5, -1)], &mut Vec::new());
+        for index in (0..3) {
+            let (mut messages, mut internal) = (Vec::new(), Vec::new());
+            progcasters[index].send_and_recv(&mut messages, &mut internal);
+            assert_eq!(messages, vec![]);
+            assert_eq!(internal, vec![]);
+        }
+    }
+}
@@ -4,6 +4,8 @@ pub use progress::subgraph::Subgraph;
 pub use progress::timestamp::{Timestamp, PathSummary};
 pub use progress::count_map::CountMap;
 pub use progress::frontier::Antichain;
+pub use progress::activator::Activator;
+pub use progress::scheduling::{SchedulingPolicy, Candidate, RoundRobin, EarliestFirst, Weighted};
 
 pub mod count_map;
 pub mod frontier;
@@ -13,3 +15,5 @@ pub mod timestamp;
 pub mod scope;
 pub mod broadcast;
 pub mod notificator;
+pub mod activator;
+pub mod scheduling;
@@ -0,0 +1,98 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// What a scheduling policy knows about a child scope that may have something to do in a step.
+pub struct Candidate<T> {
+    pub index:      u64,            // of the scope in its subgraph
+    pub earliest:   Option<T>,      // an earliest time among the messages waiting at its inputs, if any
+}
+
+// Decides the order in which a subgraph schedules its children in each step. without a policy, children are
+// scheduled in the order they were added.
+pub trait SchedulingPolicy<T> {
+    // appends to order the indices of the candidates, which are in increasing order of index, in the order in which
+    // to schedule them. a candidate appearing more than once is scheduled again if it still has something to do.
+    fn order(&mut self, candidates: &[Candidate<T>], order: &mut Vec<u64>);
+}
+
+// starts each step from the candidate after the one the previous step started from, so that no scope is always
+// scheduled after the same others.
+#[derive(Default)]
+pub struct RoundRobin {
+    next:   u64,    // the least index to start from
+}
+
+impl<T> SchedulingPolicy<T> for RoundRobin {
+    fn order(&mut self, candidates: &[Candidate<T>], order: &mut Vec<u64>) {
+        let start = candidates.iter().position(|candidate| candidate.index >= self.next).unwrap_or(0);
+        order.extend(candidates[start..].iter().chain(candidates[..start].iter()).map(|candidate| candidate.index));
+        if let Some(first) = candidates.get(start) { self.next = first.index + 1; }
+    }
+}
+
+// schedules scopes with messages at earlier times first, and scopes without waiting messages last. scopes whose
+// times are not comparable keep their order of index.
+#[derive(Default)]
+pub struct EarliestFirst;
+
+impl<T: PartialOrd> SchedulingPolicy<T> for EarliestFirst {
+    fn order(&mut self, candidates: &[Candidate<T>], order: &mut Vec<u64>) {
+        let mut sorted: Vec<&Candidate<T>> = candidates.iter().collect();
+        sorted.sort_by(|x, y| match (&x.earliest, &y.earliest) {
+            (&Some(ref x), &Some(ref y))    => x.partial_cmp(y).unwrap_or(Ordering::Equal),
+            (&Some(_), &None)               => Ordering::Less,
+            (&None, &Some(_))               => Ordering::Greater,
+            (&None, &None)                  => Ordering::Equal,
+        });
+        order.extend(sorted.iter().map(|candidate| candidate.index));
+    }
+}
+
+// gives each scope as many turns in a step as its weight, one by default. turns are interleaved, so that in each
+// round every scope with turns left takes one, in order of index.
+#[derive(Default)]
+pub struct Weighted {
+    weights:    HashMap<u64, u64>,  // turns per step, by index of scope
+}
+
+impl Weighted {
+    pub fn new() -> Weighted { Default::default() }
+    pub fn weight(mut self, index: u64, weight: u64) -> Weighted {
+        assert!(weight > 0, "scope {}: a weight of zero would never schedule it", index);
+        self.weights.insert(index, weight);
+        self
+    }
+}
+
+impl<T> SchedulingPolicy<T> for Weighted {
+    fn order(&mut self, candidates: &[Candidate<T>], order: &mut Vec<u64>) {
+        let weights = &self.weights;
+        let turns = |candidate: &Candidate<T>| *weights.get(&candidate.index).unwrap_or(&1);
+        let rounds = candidates.iter().map(|candidate| turns(candidate)).max().unwrap_or(0);
+        for round in (0..rounds) {
+            order.extend(candidates.iter().filter(|&candidate| turns(candidate) > round).map(|candidate| candidate.index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Candidate, SchedulingPolicy, RoundRobin, EarliestFirst, Weighted};
+
+    #[test]
+    fn policies_order_candidates() {
+        let candidates = vec![Candidate { index: 0, earliest: None },
+                              Candidate { index: 2, earliest: Some(5u64) },
+                              Candidate { index: 3, earliest: Some(1u64) },
+                              Candidate { index: 7, earliest: Some(5u64) }];
+        let order = |policy: &mut SchedulingPolicy<u64>| { let mut order = Vec::new(); policy.order(&candidates[..], &mut order); order };
+
+        let mut round_robin = RoundRobin::default();
+        assert_eq!(order(&mut round_robin), vec![0, 2, 3, 7]);
+        assert_eq!(order(&mut round_robin), vec![2, 3, 7, 0]);
+        assert_eq!(order(&mut round_robin), vec![3, 7, 0, 2]);
+
+        assert_eq!(order(&mut EarliestFirst), vec![3, 2, 7, 0]);
+        assert_eq!(order(&mut Weighted::new().weight(3, 3).weight(7, 2)), vec![0, 2, 3, 7, 3, 7, 3]);
+    }
+}
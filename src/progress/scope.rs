@@ -1,6 +1,6 @@
 use std::default::Default;
 
-use progress::{Timestamp, CountMap, Antichain};
+use progress::{Timestamp, CountMap, Antichain, Activator};
 
 pub trait Scope<T: Timestamp> {
     fn inputs(&self) -> u64;               // number of inputs to the vertex.
@@ -32,4 +32,9 @@ pub trait Scope<T: Timestamp> {
 
     fn name(&self) -> String;               // something descriptive and helpful.
     fn notify_me(&self) -> bool { true }    // override to false if no interest in push_external_progress().
+
+    // override to true to be scheduled only when messages wait at an input, push_external_progress() reported a
+    // change, pull_internal_progress() returned true, or the scope asked to be through the activator it was given.
+    fn schedule_on_activation(&self) -> bool { false }
+    fn set_activator(&mut self, _activator: Activator) { }
 }
@@ -7,6 +7,10 @@ use std::mem;
 use std::rc::Rc;
 use std::cell::RefCell;
 use communication::Communicator;
+use networking::Failure;
+
+use progress::Activator;
+use progress::scheduling::{SchedulingPolicy, Candidate};
 
 use progress::frontier::{MutableAntichain, Antichain};
 use progress::{Timestamp, PathSummary, Graph, Scope};
@@ -16,7 +20,7 @@ use progress::subgraph::Target::{GraphOutput, ScopeInput};
 use progress::subgraph::Summary::{Local, Outer};
 use progress::count_map::CountMap;
 
-use progress::broadcast::{Progcaster, ProgressVec};
+use progress::broadcast::{Progcaster, ProgressVec, ProgressProtocol};
 
 #[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
 pub enum Source {
@@ -112,6 +116,8 @@ pub struct ScopeWrapper<T: Timestamp> {
     edges:                  Vec<Vec<Target>>,
 
     notify:                 bool,
+    on_activation:          bool,                      // scheduled only when there may be something to do
+    scheduled:              bool,                      // to be scheduled in the next step, whatever its inputs
     summary:                Vec<Vec<Antichain<T::Summary>>>,     // internal path summaries (input x output)
 
     guarantees:             Vec<MutableAntichain<T>>,   // per-input:   guarantee made by parent scope in inputs
@@ -126,10 +132,12 @@ pub struct ScopeWrapper<T: Timestamp> {
 }
 
 impl<T: Timestamp> ScopeWrapper<T> {
-    fn new(scope: Box<Scope<T>>, index: u64) -> ScopeWrapper<T> {
+    fn new(mut scope: Box<Scope<T>>, index: u64, activator: Activator) -> ScopeWrapper<T> {
+        scope.set_activator(activator);
         let inputs = scope.inputs();
         let outputs = scope.outputs();
         let notify = scope.notify_me();
+        let on_activation = scope.schedule_on_activation();
 
         let mut result = ScopeWrapper {
             scope:      scope,
@@ -139,6 +147,8 @@ impl<T: Timestamp> ScopeWrapper<T> {
             edges:      vec![Default::default(); outputs as usize],
 
             notify:     notify,
+            on_activation: on_activation,
+            scheduled:  true,
             summary:    Vec::new(),
 
             guarantees:             vec![Default::default(); inputs as usize],
@@ -175,9 +185,10 @@ impl<T: Timestamp> ScopeWrapper<T> {
                     .update_into_cm(&external_progress[input_port], &mut self.guarantee_changes[input_port]);
             }
 
-            // push any changes to the frontier to the subgraph.
+            // push any changes to the frontier to the subgraph, which may then have something to do.
             if self.guarantee_changes.iter().any(|x| x.len() > 0) {
                 self.scope.push_external_progress(&mut self.guarantee_changes);
+                self.scheduled = true;
 
                 // TODO : Shouldn't be necessary
                 for change in self.guarantee_changes.iter_mut() { change.clear(); }
@@ -185,6 +196,20 @@ impl<T: Timestamp> ScopeWrapper<T> {
         }
     }
 
+    // whether to schedule the scope in this step.
+    fn schedule(&self) -> bool {
+        !self.on_activation || self.scheduled || self.outstanding_messages.iter().any(|x| x.elements.len() > 0)
+    }
+
+    // a least time among the messages waiting at the scope's inputs, if any.
+    fn earliest(&self) -> Option<T> {
+        let mut earliest: Option<T> = None;
+        for time in self.outstanding_messages.iter().flat_map(|x| x.elements.iter()) {
+            if earliest.map(|earliest| time < &earliest).unwrap_or(true) { earliest = Some(*time); }
+        }
+        earliest
+    }
+
     fn pull_pointstamps<A: FnMut(u64, T,i64)->()>(&mut self,
                                                   pointstamp_messages: &mut ProgressVec<T>,
                                                   pointstamp_internal: &mut ProgressVec<T>,
@@ -287,6 +312,12 @@ pub struct Subgraph<TOuter:Timestamp, TInner:Timestamp> {
     pointstamp_internal:    ProgressVec<(TOuter, TInner)>,
 
     progcaster:             Progcaster<(TOuter, TInner)>,
+
+    activations:            Rc<RefCell<Vec<u64>>>,      // children that have asked to be scheduled
+
+    policy:                 Option<Box<SchedulingPolicy<(TOuter, TInner)>>>,   // order of scheduling; None for order of index
+    candidates:             Vec<Candidate<(TOuter, TInner)>>,  // temp storage for children to schedule
+    schedule:               Vec<u64>,                   // temp storage for the order to schedule them in
 }
 
 
@@ -318,6 +349,17 @@ impl<TOuter: Timestamp, TInner: Timestamp> Scope<TOuter> for Subgraph<TOuter, TI
             }
         }
 
+        // a progress coordinator counts updates from the capabilities scopes start with
+        let mut capabilities = Vec::new();
+        for (index, child) in self.children.iter().enumerate() {
+            for (output, capability) in child.capabilities.iter().enumerate() {
+                for &(time, count) in capability.occurrences.elements().iter() {
+                    capabilities.push((index as u64, output as u64, time, count));
+                }
+            }
+        }
+        self.progcaster.take_capabilities(&capabilities);
+
         // initialize space for input -> Vec<(Target, Antichain) mapping.
         self.input_summaries = vec![Vec::new(); self.inputs() as usize];
 
@@ -459,13 +501,28 @@ impl<TOuter: Timestamp, TInner: Timestamp> Scope<TOuter> for Subgraph<TOuter, TI
             }
         }
 
-        // Step 2: pull_internal_progress from subscopes.
-        for child in self.children.iter_mut() {
-            let subactive = child.pull_pointstamps(&mut self.pointstamp_messages,
-                                                   &mut self.pointstamp_internal,
-                                                   |out, time, delta| { messages_produced[out as usize].update(&time.0, delta); });
+        // Step 2: pull_internal_progress from subscopes that may have something to do.
+        for index in self.activations.borrow_mut().drain() { self.children[index as usize].scheduled = true; }
+        for child in self.children.iter().filter(|child| child.schedule()) {
+            let earliest = if self.policy.is_some() { child.earliest() } else { None };
+            self.candidates.push(Candidate { index: child.index, earliest: earliest });
+        }
+        match self.policy {
+            Some(ref mut policy) => policy.order(&self.candidates[..], &mut self.schedule),
+            None                 => self.schedule.extend(self.candidates.iter().map(|candidate| candidate.index)),
+        }
+        self.candidates.clear();
+
+        for index in self.schedule.drain() {
+            let child = &mut self.children[index as usize];
+            if child.schedule() {   // a child listed again may have nothing left to do
+                child.scheduled = false;
+                let subactive = child.pull_pointstamps(&mut self.pointstamp_messages,
+                                                       &mut self.pointstamp_internal,
+                                                       |out, time, delta| { messages_produced[out as usize].update(&time.0, delta); });
 
-            if subactive { active = true; }
+                if subactive { active = true; child.scheduled = true; }
+            }
         }
 
         // Intermission: exchange pointstamp updates, and then move them to the pointstamps structure.
@@ -528,12 +585,16 @@ impl<TOuter: Timestamp, TInner: Timestamp, C: Communicator> Graph for (Rc<RefCel
     fn add_boxed_scope(&mut self, scope: Box<Scope<(TOuter, TInner)>>) -> u64 {
         let mut borrow = self.0.borrow_mut();
         let index = borrow.children.len() as u64;
-        borrow.children.push(ScopeWrapper::new(scope, index));
+        let activator = Activator::new(index, borrow.activations.clone(), borrow.progcaster.activity_counter());
+        borrow.children.push(ScopeWrapper::new(scope, index, activator));
         return index;
     }
 
     fn new_subgraph<T: Timestamp>(&mut self) -> Subgraph<(TOuter, TInner), T> {
-        let progcaster = Progcaster::new(&mut (*self.1.borrow_mut()));
+        // nested scopes exchange progress as the dataflow does
+        let protocol = self.0.borrow().progcaster.protocol();
+        let mut progcaster = Progcaster::with_protocol(&mut (*self.1.borrow_mut()), protocol);
+        progcaster.share_activity(&self.0.borrow().progcaster);
         let mut result: Subgraph<(TOuter, TInner), T> = Subgraph::new_from(progcaster);
         result.index = self.0.borrow().children() as u64;
         return result;
@@ -549,6 +610,12 @@ impl<TOuter: Timestamp, TInner: Timestamp, C: Communicator> Graph for (Rc<RefCel
 impl<TOuter: Timestamp, TInner: Timestamp> Subgraph<TOuter, TInner> {
     pub fn children(&self) -> usize { self.children.len() }
 
+    // progress traffic of this scope and its nested scopes so far; unchanged by a step in which nothing happened.
+    pub fn activity(&self) -> u64 { self.progcaster.activity() }
+
+    // replaces the order in which children with something to do are scheduled in each step.
+    pub fn set_scheduling_policy(&mut self, policy: Box<SchedulingPolicy<(TOuter, TInner)>>) { self.policy = Some(policy); }
+
     fn push_pointstamps_to_targets(&mut self) -> () {
         for index in (0..self.children.len()) {
             for input in (0..self.pointstamps.target_counts[index].len()) {
@@ -751,6 +818,10 @@ impl<TOuter: Timestamp, TInner: Timestamp> Subgraph<TOuter, TInner> {
             pointstamp_messages:    Default::default(),
             pointstamp_internal:    Default::default(),
             progcaster:             progcaster,
+            activations:            Default::default(),
+            policy:                 None,
+            candidates:             Default::default(),
+            schedule:               Default::default(),
         }
     }
 }
@@ -760,6 +831,30 @@ pub fn new_graph<T: Timestamp, C: Communicator>(mut communicator: C) -> (Rc<RefC
     return (Rc::new(RefCell::new(Subgraph::new_from(progcaster))), Rc::new(RefCell::new(communicator)));
 }
 
+// as new_graph, but with the dataflow's progress exchanged by the supplied protocol rather than the communicator's.
+pub fn new_graph_with_protocol<T: Timestamp, C: Communicator>(mut communicator: C, protocol: ProgressProtocol) -> (Rc<RefCell<Subgraph<(), T>>>, Rc<RefCell<C>>) {
+    let progcaster = Progcaster::with_protocol(&mut communicator, protocol);
+    return (Rc::new(RefCell::new(Subgraph::new_from(progcaster))), Rc::new(RefCell::new(communicator)));
+}
+
+// longest a worker parks for lack of work; bounds the wait for events that do not signal (e.g. credit for a backlog).
+pub const PARK_TIMEOUT_MS: u32 = 100;
+
+// performs one round of progress, returning whether the dataflow is still active, or the failure (of a peer process,
+// or a mismatch between workers' dataflows) from which it cannot complete. the dataflow is taken to be built by its
+// first step. a worker whose dataflow exchanged no progress updates has nothing to do, and parks until a message
+// arrives for it rather than spinning.
+pub fn step<T: Timestamp, C: Communicator>(graph: &(Rc<RefCell<Subgraph<(), T>>>, Rc<RefCell<C>>)) -> Result<bool, Failure> {
+    graph.1.borrow_mut().allocation_complete();
+    if let Some(failure) = graph.1.borrow().failure() { return Err(failure); }
+    let activity = graph.0.borrow().activity();
+    let active = graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new());
+    if active && graph.0.borrow().activity() == activity && graph.1.borrow().backlog() == 0 {
+        if let Some(signal) = graph.1.borrow().signal() { signal.wait(PARK_TIMEOUT_MS); }
+    }
+    Ok(active)
+}
+
 fn try_to_add_summary<S: PartialOrd+Eq+Copy+Debug>(vector: &mut Vec<(Target, Antichain<S>)>, target: Target, summary: S) -> bool {
     for &mut (ref t, ref mut antichain) in vector.iter_mut() {
         if target.eq(t) { return antichain.insert(summary); }
@@ -767,3 +862,52 @@ fn try_to_add_summary<S: PartialOrd+Eq+Copy+Debug>(vector: &mut Vec<(Target, Ant
     vector.push((target, Antichain::from_elem(summary)));
     return true;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    use progress::{Activator, Graph, Scope};
+    use progress::count_map::CountMap;
+
+    use super::new_graph;
+
+    #[test]
+    fn idle_scopes_are_scheduled_only_when_activated() {
+        use std::cell::Cell;
+        use communication::ThreadCommunicator;
+
+        // a scope with no inputs or outputs, counting the steps it is scheduled in.
+        struct Counter { pulls: Rc<Cell<u64>>, activator: Rc<RefCell<Option<Activator>>> }
+        impl Scope<((), u64)> for Counter {
+            fn name(&self) -> String { format!("Counter") }
+            fn inputs(&self) -> u64 { 0 }
+            fn outputs(&self) -> u64 { 0 }
+            fn pull_internal_progress(&mut self, _: &mut Vec<CountMap<((), u64)>>,
+                                                 _: &mut Vec<CountMap<((), u64)>>,
+                                                 _: &mut Vec<CountMap<((), u64)>>) -> bool {
+                self.pulls.set(self.pulls.get() + 1);
+                false
+            }
+            fn schedule_on_activation(&self) -> bool { true }
+            fn set_activator(&mut self, activator: Activator) { *self.activator.borrow_mut() = Some(activator); }
+        }
+
+        let mut graph = new_graph::<u64, _>(ThreadCommunicator);
+        let pulls = Rc::new(Cell::new(0));
+        let activator = Rc::new(RefCell::new(None));
+        graph.add_scope(Counter { pulls: pulls.clone(), activator: activator.clone() });
+        graph.0.borrow_mut().get_internal_summary();
+        graph.0.borrow_mut().set_external_summary(Vec::new(), &mut Vec::new());
+        graph.0.borrow_mut().push_external_progress(&mut Vec::new());
+
+        let step = || { graph.0.borrow_mut().pull_internal_progress(&mut Vec::new(), &mut Vec::new(), &mut Vec::new()); };
+        for _ in (0..5) { step(); }
+        assert_eq!(pulls.get(), 1);     // scheduled in its first step, and then not again
+
+        activator.borrow().as_ref().unwrap().activate();
+        for _ in (0..5) { step(); }
+        assert_eq!(pulls.get(), 2);
+    }
+}